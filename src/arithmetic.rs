@@ -0,0 +1,460 @@
+use std::fmt;
+
+use crate::shell::Shell;
+
+// How deep variables holding expressions may refer to each other, `a=b b=a` would never end.
+const MAX_DEPTH: usize = 64;
+
+// Integer arithmetic of `$((...))`, `((...))` and `let`: the C operators
+// with their precedence, on 64-bit integers that wrap around. Variables are
+// read and assigned in the shell's table; an unset or empty one is 0, and a
+// value that isn't a number is evaluated as an expression itself.
+pub struct Arithmetic;
+
+#[derive(Debug, Clone)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+}
+
+#[derive(Debug)]
+enum Expr {
+    Number(i64),
+    Variable(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    // `name = value`, `name += value`, ...: the operator without its `=`, "" for plain `=`
+    Assign(String, &'static str, Box<Expr>),
+    // `++name` and `--name` with `prefix`, `name++` and `name--` without
+    Increment { name: String, delta: i64, prefix: bool },
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+// Longest first, so that `<<=` isn't read as `<` and `<=`.
+const OPERATORS: [&str; 40] = [
+    "<<=", ">>=", "**=", "++", "--", "**", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=",
+    "-=", "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "^", "|",
+    "!", "~", "?", ":", "=", ",", "(", ")",
+];
+
+// Binary operators from the loosest to the tightest binding, all left associative.
+const LEVELS: [&[&str]; 10] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", ">", "<=", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+impl Arithmetic {
+    // Evaluates the expression, already expanded, and returns its value.
+    pub fn evaluate(shell: &mut Shell, expression: &str) -> Result<i64, String> {
+        Self::evaluate_at(shell, expression, 0)
+    }
+
+    fn evaluate_at(shell: &mut Shell, expression: &str, depth: usize) -> Result<i64, String> {
+        if depth > MAX_DEPTH {
+            return Err(format!("{}: expression recursion level exceeded", expression.trim()));
+        }
+        // errors name the expression they were found in, like `1 +: syntax error`
+        let error = |message: String| format!("{}: {}", expression.trim(), message);
+        let tokens = tokenize(expression).map_err(error)?;
+        if tokens.is_empty() {
+            return Ok(0);
+        }
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.comma().map_err(error)?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(error(format!("syntax error in expression (error token is \"{}\")", token)));
+        }
+        Evaluator { shell, depth }.eval(&expr).map_err(error)
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Operator(operator) => write!(f, "{}", operator),
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expression.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '#' && c != '@' && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(number(&rest[..len])?));
+            len
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..len].to_string()));
+            len
+        } else if let Some(operator) = OPERATORS.into_iter().find(|operator| rest.starts_with(operator)) {
+            tokens.push(Token::Operator(operator));
+            operator.len()
+        } else {
+            return Err(format!("syntax error: invalid arithmetic operator (error token is \"{}\")", rest));
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+// A number: decimal, octal with a leading 0, hexadecimal with 0x, or
+// `base#digits` for any base from 2 to 64.
+fn number(text: &str) -> Result<i64, String> {
+    let invalid = || format!("value too great for base (error token is \"{}\")", text);
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        match base.parse::<u32>() {
+            Ok(base) if (2..=64).contains(&base) => (base, digits),
+            _ => return Err(format!("invalid arithmetic base (error token is \"{}\")", text)),
+        }
+    } else if let Some(digits) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (16, digits)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        // up to base 36 letters of either case are digits from 10 on; above
+        // it a-z, A-Z, @ and _ stand for 10 to 63
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return Err(invalid()),
+        };
+        if digit >= base {
+            return Err(invalid());
+        }
+        value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+    Ok(value)
+}
+
+// Recursive descent over the tokens, one method per precedence level.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, operator: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Operator(op)) if *op == operator);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn unexpected(&self) -> String {
+        match self.peek() {
+            Some(token) => format!("syntax error: operand expected (error token is \"{}\")", token),
+            None => "syntax error: operand expected".to_string(),
+        }
+    }
+
+    // expression, expression, ...: the value of the last one
+    fn comma(&mut self) -> Result<Expr, String> {
+        let mut expr = self.assignment()?;
+        while self.eat(",") {
+            expr = Expr::Binary(",", Box::new(expr), Box::new(self.assignment()?));
+        }
+        Ok(expr)
+    }
+
+    fn assignment(&mut self) -> Result<Expr, String> {
+        if let (Some(Token::Name(name)), Some(Token::Operator(operator))) =
+            (self.tokens.get(self.pos), self.tokens.get(self.pos + 1))
+        {
+            if let Some(operator) = operator.strip_suffix('=').filter(|op| !matches!(*op, "=" | "!" | "<" | ">")) {
+                let name = name.clone();
+                self.pos += 2;
+                let value = self.assignment()?;
+                return Ok(Expr::Assign(name, operator, Box::new(value)));
+            }
+        }
+        self.conditional()
+    }
+
+    fn conditional(&mut self) -> Result<Expr, String> {
+        let condition = self.binary(0)?;
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        let then = self.comma()?;
+        if !self.eat(":") {
+            return Err(self.unexpected());
+        }
+        let otherwise = self.assignment()?;
+        Ok(Expr::Conditional(Box::new(condition), Box::new(then), Box::new(otherwise)))
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        let Some(operators) = LEVELS.get(level) else {
+            return self.power();
+        };
+        let mut left = self.binary(level + 1)?;
+        while let Some(&operator) = operators.iter().find(|&&operator| self.eat(operator)) {
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    // `**` binds tighter than `*` and associates to the right
+    fn power(&mut self) -> Result<Expr, String> {
+        let base = self.unary()?;
+        if self.eat("**") {
+            return Ok(Expr::Binary("**", Box::new(base), Box::new(self.power()?)));
+        }
+        Ok(base)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        for operator in ["++", "--"] {
+            if self.eat(operator) {
+                let Some(Token::Name(name)) = self.peek().cloned() else {
+                    return Err(self.unexpected());
+                };
+                self.pos += 1;
+                let delta = if operator == "++" { 1 } else { -1 };
+                return Ok(Expr::Increment { name, delta, prefix: true });
+            }
+        }
+        for operator in ["!", "~", "-", "+"] {
+            if self.eat(operator) {
+                return Ok(Expr::Unary(operator, Box::new(self.unary()?)));
+            }
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let expr = match self.peek().cloned() {
+            Some(Token::Number(n)) => Expr::Number(n),
+            Some(Token::Name(name)) => Expr::Variable(name),
+            Some(Token::Operator("(")) => {
+                self.pos += 1;
+                let expr = self.comma()?;
+                if !self.eat(")") {
+                    return Err(self.unexpected());
+                }
+                return Ok(expr);
+            }
+            _ => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        if let Expr::Variable(name) = &expr {
+            for (operator, delta) in [("++", 1), ("--", -1)] {
+                if self.eat(operator) {
+                    let name = name.clone();
+                    return Ok(Expr::Increment { name, delta, prefix: false });
+                }
+            }
+        }
+        Ok(expr)
+    }
+}
+
+struct Evaluator<'a> {
+    shell: &'a mut Shell,
+    depth: usize,
+}
+
+impl Evaluator<'_> {
+    fn eval(&mut self, expr: &Expr) -> Result<i64, String> {
+        Ok(match expr {
+            Expr::Number(n) => *n,
+            Expr::Variable(name) => self.variable(name)?,
+            Expr::Unary(operator, operand) => {
+                let value = self.eval(operand)?;
+                match *operator {
+                    "!" => (value == 0) as i64,
+                    "~" => !value,
+                    "-" => value.wrapping_neg(),
+                    _ => value,
+                }
+            }
+            // the right side only runs when it decides the result
+            Expr::Binary("&&", left, right) => (self.eval(left)? != 0 && self.eval(right)? != 0) as i64,
+            Expr::Binary("||", left, right) => (self.eval(left)? != 0 || self.eval(right)? != 0) as i64,
+            Expr::Binary(operator, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                apply(operator, left, right)?
+            }
+            Expr::Assign(name, operator, value) => {
+                let mut value = self.eval(value)?;
+                if !operator.is_empty() {
+                    value = apply(operator, self.variable(name)?, value)?;
+                }
+                self.assign(name, value)?
+            }
+            Expr::Increment { name, delta, prefix } => {
+                let old = self.variable(name)?;
+                let new = self.assign(name, old.wrapping_add(*delta))?;
+                if *prefix {
+                    new
+                } else {
+                    old
+                }
+            }
+            Expr::Conditional(condition, then, otherwise) => match self.eval(condition)? {
+                0 => self.eval(otherwise)?,
+                _ => self.eval(then)?,
+            },
+        })
+    }
+
+    fn variable(&mut self, name: &str) -> Result<i64, String> {
+        let value = self.shell.variables.get(name).unwrap_or_default().trim().to_string();
+        if value.is_empty() {
+            return Ok(0);
+        }
+        match number(&value) {
+            Ok(n) => Ok(n),
+            Err(_) => Arithmetic::evaluate_at(self.shell, &value, self.depth + 1),
+        }
+    }
+
+    fn assign(&mut self, name: &str, value: i64) -> Result<i64, String> {
+        self.shell.variables.set(name, value.to_string())?;
+        Ok(value)
+    }
+}
+
+fn apply(operator: &str, left: i64, right: i64) -> Result<i64, String> {
+    Ok(match operator {
+        "," => right,
+        "|" => left | right,
+        "^" => left ^ right,
+        "&" => left & right,
+        "==" => (left == right) as i64,
+        "!=" => (left != right) as i64,
+        "<" => (left < right) as i64,
+        ">" => (left > right) as i64,
+        "<=" => (left <= right) as i64,
+        ">=" => (left >= right) as i64,
+        "<<" => left.wrapping_shl(right as u32),
+        ">>" => left.wrapping_shr(right as u32),
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" | "%" if right == 0 => return Err("division by 0".to_string()),
+        "/" => left.wrapping_div(right),
+        "%" => left.wrapping_rem(right),
+        "**" if right < 0 => return Err("exponent less than 0".to_string()),
+        "**" => left.wrapping_pow(right.min(u32::MAX as i64) as u32),
+        _ => unreachable!("not a binary operator: {}", operator),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(shell: &mut Shell, expression: &str) -> Result<i64, String> {
+        Arithmetic::evaluate(shell, expression)
+    }
+
+    fn value(expression: &str) -> i64 {
+        eval(&mut Shell::new(), expression).unwrap()
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(value("1 + 2 * 3"), 7);
+        assert_eq!(value("(1 + 2) * 3"), 9);
+        assert_eq!(value("10 - 4 - 3"), 3);
+        assert_eq!(value("2 ** 3 ** 2"), 512);
+        assert_eq!(value("-2 ** 2"), 4);
+        assert_eq!(value("1 << 2 + 1"), 8);
+        assert_eq!(value("1 | 2 ^ 3 & 1"), 3);
+        assert_eq!(value("1 < 2 == 1"), 1);
+        assert_eq!(value("!0 + ~0"), 0);
+        assert_eq!(value("0 || 2 && 3"), 1);
+        assert_eq!(value("1 ? 2 : 3 ? 4 : 5"), 2);
+        assert_eq!(value("0 ? 2 : 0 ? 4 : 5"), 5);
+        assert_eq!(value("1, 2, 3"), 3);
+        assert_eq!(value(""), 0);
+    }
+
+    #[test]
+    fn bases() {
+        assert_eq!(value("010"), 8);
+        assert_eq!(value("0x1F"), 31);
+        assert_eq!(value("2#101"), 5);
+        assert_eq!(value("16#ff"), 255);
+        assert_eq!(value("36#Z"), 35);
+        assert_eq!(value("64#A"), 36);
+        assert_eq!(value("64#@"), 62);
+        assert_eq!(value("64#_"), 63);
+        let mut shell = Shell::new();
+        assert!(eval(&mut shell, "08").unwrap_err().contains("value too great for base"));
+        assert!(eval(&mut shell, "2#2").unwrap_err().contains("value too great for base"));
+        assert!(eval(&mut shell, "65#1").unwrap_err().contains("invalid arithmetic base"));
+    }
+
+    #[test]
+    fn errors() {
+        let mut shell = Shell::new();
+        assert_eq!(eval(&mut shell, "2 ** -1").unwrap_err(), "2 ** -1: exponent less than 0");
+        assert_eq!(eval(&mut shell, "1 / 0").unwrap_err(), "1 / 0: division by 0");
+        assert_eq!(eval(&mut shell, "5 % 0").unwrap_err(), "5 % 0: division by 0");
+        assert!(eval(&mut shell, "1 +").unwrap_err().starts_with("1 +: "));
+        assert!(eval(&mut shell, "(1").is_err());
+        assert!(eval(&mut shell, "1 2").unwrap_err().contains("error token is \"2\""));
+    }
+
+    #[test]
+    fn wraps_around() {
+        assert_eq!(value("9223372036854775807 + 1"), i64::MIN);
+        assert_eq!(value("-9223372036854775807 - 2"), i64::MAX);
+        assert_eq!(value("2 ** 64"), 0);
+    }
+
+    #[test]
+    fn variables() {
+        let mut shell = Shell::new();
+        assert_eq!(eval(&mut shell, "arith_n = 5, arith_n += 2, arith_n *= 3").unwrap(), 21);
+        assert_eq!(shell.variables.get("arith_n"), Some("21"));
+        assert_eq!(eval(&mut shell, "arith_n++").unwrap(), 21);
+        assert_eq!(eval(&mut shell, "++arith_n").unwrap(), 23);
+        assert_eq!(eval(&mut shell, "arith_n--, --arith_n").unwrap(), 21);
+        assert_eq!(eval(&mut shell, "arith_unset + 1").unwrap(), 1);
+        shell.variables.set("arith_expr", "arith_n * 2".to_string()).unwrap();
+        assert_eq!(eval(&mut shell, "arith_expr + 1").unwrap(), 43);
+        shell.variables.set("arith_loop", "arith_loop".to_string()).unwrap();
+        assert!(eval(&mut shell, "arith_loop").unwrap_err().contains("recursion level exceeded"));
+        // the right side of `&&` and `||` only runs when it decides the result
+        assert_eq!(eval(&mut shell, "0 && (arith_n = 0)").unwrap(), 0);
+        assert_eq!(shell.variables.get("arith_n"), Some("21"));
+    }
+}
@@ -1,3 +1,7 @@
+use std::borrow::Cow;
+
+use crate::parser::{ParseError, Parser};
+use crate::tokenizer::Tokenizer;
 use rustyline::completion::{Completer, Pair};
 use rustyline::highlight::Highlighter;
 use rustyline::hint::Hinter;
@@ -16,6 +20,13 @@ impl AutoCompleter {
         }
     }
 }
+
+impl Default for AutoCompleter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Completer for AutoCompleter {
     type Candidate = Pair;
     
@@ -23,7 +34,7 @@ impl Completer for AutoCompleter {
         &self,
         line: &str,
         pos: usize,
-        ctx: &Context<'_>,
+        _ctx: &Context<'_>,
     ) -> rustyline::Result<(usize, Vec<Pair>)> {
         let start = 0 ; 
         let prefix = &line[..pos];
@@ -52,19 +63,36 @@ impl Hinter for AutoCompleter {
     fn hint(&self, _line: &str, _pos: usize, _ctx: &Context<'_>) -> Option<Self::Hint> { None }
 }
 
-impl Highlighter for AutoCompleter {}
+impl Highlighter for AutoCompleter {
+    // Paints the token a syntax error points at in red while the line is typed.
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        match Parser::parse(Tokenizer::tokenize(line, 1)) {
+            Err(ParseError::UnexpectedToken { span, .. }) if span.start < span.end => {
+                let (before, rest) = line.split_at(span.start);
+                let (token, after) = rest.split_at(span.end - span.start);
+                Cow::Owned(format!("{}\x1b[1;31m{}\x1b[0m{}", before, token, after))
+            }
+            _ => Cow::Borrowed(line),
+        }
+    }
+
+    // every keystroke can make or fix a syntax error
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        true
+    }
+}
 impl Validator for AutoCompleter {}
 impl Helper for AutoCompleter {}
 impl History for AutoCompleter{
-    fn get(&self, index: usize, dir: rustyline::history::SearchDirection) -> rustyline::Result<Option<rustyline::history::SearchResult>> {
+    fn get(&self, _index: usize, _dir: rustyline::history::SearchDirection) -> rustyline::Result<Option<rustyline::history::SearchResult<'_>>> {
         todo!()
     }
 
-    fn add(&mut self, line: &str) -> rustyline::Result<bool> {
+    fn add(&mut self, _line: &str) -> rustyline::Result<bool> {
         todo!()
     }
 
-    fn add_owned(&mut self, line: String) -> rustyline::Result<bool> {
+    fn add_owned(&mut self, _line: String) -> rustyline::Result<bool> {
         todo!()
     }
 
@@ -76,27 +104,27 @@ impl History for AutoCompleter{
         todo!()
     }
 
-    fn set_max_len(&mut self, len: usize) -> rustyline::Result<()> {
+    fn set_max_len(&mut self, _len: usize) -> rustyline::Result<()> {
         todo!()
     }
 
-    fn ignore_dups(&mut self, yes: bool) -> rustyline::Result<()> {
+    fn ignore_dups(&mut self, _yes: bool) -> rustyline::Result<()> {
         todo!()
     }
 
-    fn ignore_space(&mut self, yes: bool) {
+    fn ignore_space(&mut self, _yes: bool) {
         todo!()
     }
 
-    fn save(&mut self, path: &std::path::Path) -> rustyline::Result<()> {
+    fn save(&mut self, _path: &std::path::Path) -> rustyline::Result<()> {
         todo!()
     }
 
-    fn append(&mut self, path: &std::path::Path) -> rustyline::Result<()> {
+    fn append(&mut self, _path: &std::path::Path) -> rustyline::Result<()> {
         todo!()
     }
 
-    fn load(&mut self, path: &std::path::Path) -> rustyline::Result<()> {
+    fn load(&mut self, _path: &std::path::Path) -> rustyline::Result<()> {
         todo!()
     }
 
@@ -106,19 +134,19 @@ impl History for AutoCompleter{
 
     fn search(
         &self,
-        term: &str,
-        start: usize,
-        dir: rustyline::history::SearchDirection,
-    ) -> rustyline::Result<Option<rustyline::history::SearchResult>> {
+        _term: &str,
+        _start: usize,
+        _dir: rustyline::history::SearchDirection,
+    ) -> rustyline::Result<Option<rustyline::history::SearchResult<'_>>> {
         todo!()
     }
 
     fn starts_with(
         &self,
-        term: &str,
-        start: usize,
-        dir: rustyline::history::SearchDirection,
-    ) -> rustyline::Result<Option<rustyline::history::SearchResult>> {
+        _term: &str,
+        _start: usize,
+        _dir: rustyline::history::SearchDirection,
+    ) -> rustyline::Result<Option<rustyline::history::SearchResult<'_>>> {
         todo!()
     }
 }
@@ -0,0 +1,292 @@
+use crate::tokenizer::{substitution_len, Quoting, Segment, Word};
+
+// The most words a sequence makes; a longer one is left as it is.
+const MAX_SEQUENCE: u64 = 1_000_000;
+
+// A character of a word with how it was quoted. Only an unquoted one outside
+// of a substitution can be part of a brace expression. `c` is None for a
+// quoted part with nothing in it, which still makes an argument of `""`.
+#[derive(Debug, Clone, Copy)]
+struct Char {
+    c: Option<char>,
+    quoting: Quoting,
+    special: bool,
+}
+
+// Brace expansion: `a{b,c}d` becomes `abd acd`, `{1..3}` becomes `1 2 3`.
+// It runs before every other expansion and only looks at the text as typed,
+// so `{$a,$b}` gives `$a $b`, expanded later, while `"{a,b}"` and `${a}` are
+// left alone. A word without a valid expression comes back unchanged.
+pub fn expand(word: &Word) -> Vec<Word> {
+    let chars = characters(word);
+    if !chars.iter().any(|c| c.special && c.c == Some('{')) {
+        return vec![word.clone()];
+    }
+    expand_chars(&chars).iter().map(|chars| to_word(chars)).collect()
+}
+
+// Expands the first expression and then, in each result, the ones after it.
+// A `{` that doesn't start one is kept as it is.
+fn expand_chars(chars: &[Char]) -> Vec<Vec<Char>> {
+    for open in 0..chars.len() {
+        if !(chars[open].special && chars[open].c == Some('{')) {
+            continue;
+        }
+        let Some((close, alternatives)) = brace(chars, open) else {
+            continue;
+        };
+        let mut words = Vec::new();
+        for mut alternative in alternatives {
+            alternative.extend_from_slice(&chars[close + 1..]);
+            for rest in expand_chars(&alternative) {
+                let mut word = chars[..open].to_vec();
+                word.extend(rest);
+                words.push(word);
+            }
+        }
+        return words;
+    }
+    vec![chars.to_vec()]
+}
+
+// Reads the expression starting with the `{` at `open`: the index of its `}`
+// and what it stands for, the comma-separated alternatives or the values of
+// a sequence. None when it's neither, like `{a}` or an unclosed `{a,b`.
+fn brace(chars: &[Char], open: usize) -> Option<(usize, Vec<Vec<Char>>)> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    for (i, c) in chars.iter().enumerate().skip(open) {
+        if !c.special {
+            continue;
+        }
+        match c.c {
+            Some('{') => depth += 1,
+            Some(',') if depth == 1 => commas.push(i),
+            Some('}') => {
+                depth -= 1;
+                if depth > 0 {
+                    continue;
+                }
+                if commas.is_empty() {
+                    return Some((i, sequence(&chars[open + 1..i])?));
+                }
+                let bounds = std::iter::once(open).chain(commas).chain(std::iter::once(i));
+                let bounds: Vec<usize> = bounds.collect();
+                let alternatives = bounds
+                    .windows(2)
+                    .map(|pair| chars[pair[0] + 1..pair[1]].to_vec())
+                    .collect();
+                return Some((i, alternatives));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// The values of `x..y` or `x..y..step`, between two integers or two single
+// characters. Counts down when `y` is smaller, and pads numbers with zeros to
+// the same width when either end has a leading zero, as in `{01..10}`.
+fn sequence(chars: &[Char]) -> Option<Vec<Vec<Char>>> {
+    if !chars.iter().all(|c| c.special) {
+        return None;
+    }
+    let text: String = chars.iter().filter_map(|c| c.c).collect();
+    let ends: Vec<&str> = text.split("..").collect();
+    let (start, end, step) = match ends[..] {
+        [start, end] => (start, end, 1),
+        [start, end, step] => (start, end, step.parse::<i64>().ok()?.unsigned_abs().max(1)),
+        _ => return None,
+    };
+    let values: Vec<String> = match (start.parse::<i64>(), end.parse::<i64>()) {
+        (Ok(first), Ok(last)) => {
+            let padded = |text: &str| {
+                let digits = text.trim_start_matches('-');
+                digits.len() > 1 && digits.starts_with('0')
+            };
+            let width = match padded(start) || padded(end) {
+                true => start.len().max(end.len()),
+                false => 0,
+            };
+            range(first, last, step)?
+                .map(|n| format!("{:0width$}", n, width = width))
+                .collect()
+        }
+        _ => {
+            let (mut first, mut last) = (start.chars(), end.chars());
+            let (Some(first), None, Some(last), None) =
+                (first.next(), first.next(), last.next(), last.next())
+            else {
+                return None;
+            };
+            if !first.is_ascii_alphabetic() || !last.is_ascii_alphabetic() {
+                return None;
+            }
+            range(first as i64, last as i64, step)?
+                .filter_map(|c| char::from_u32(c as u32))
+                .map(String::from)
+                .collect()
+        }
+    };
+    let literal = |text: String| {
+        text.chars()
+            .map(|c| Char {
+                c: Some(c),
+                quoting: Quoting::Single,
+                special: false,
+            })
+            .collect()
+    };
+    Some(values.into_iter().map(literal).collect())
+}
+
+// From `first` to `last` included, by `step` in the direction of `last`.
+// None when that is more than MAX_SEQUENCE values.
+fn range(first: i64, last: i64, step: u64) -> Option<impl Iterator<Item = i64>> {
+    let count = (first.abs_diff(last) / step).checked_add(1)?;
+    if count > MAX_SEQUENCE {
+        return None;
+    }
+    let step = if last < first { -(step as i64) } else { step as i64 };
+    // wrapping, the values themselves all fit between `first` and `last`
+    Some((0..count).map(move |i| first.wrapping_add(step.wrapping_mul(i as i64))))
+}
+
+// The characters of the word, those of its substitutions marked as not special.
+fn characters(word: &Word) -> Vec<Char> {
+    let mut chars = Vec::new();
+    for segment in &word.segments {
+        let quoting = segment.quoting;
+        if segment.text.is_empty() {
+            chars.push(Char {
+                c: None,
+                quoting,
+                special: false,
+            });
+            continue;
+        }
+        let text = &segment.text;
+        let mut i = 0;
+        while let Some(c) = text[i..].chars().next() {
+            let rest = &text[i..];
+            let opens = ["$(", "${", "`", "<(", ">("].iter().any(|open| rest.starts_with(open));
+            // a substitution's braces and commas are its own
+            let substitution = match quoting {
+                Quoting::Bare if opens => substitution_len(rest, false),
+                _ => None,
+            };
+            let len = substitution.unwrap_or(c.len_utf8());
+            chars.extend(rest[..len].chars().map(|c| Char {
+                c: Some(c),
+                quoting,
+                special: quoting == Quoting::Bare && substitution.is_none(),
+            }));
+            i += len;
+        }
+    }
+    chars
+}
+
+// Puts the characters back into segments, one for each run quoted the same way.
+fn to_word(chars: &[Char]) -> Word {
+    let mut segments: Vec<Segment> = Vec::new();
+    for c in chars {
+        match (segments.last_mut(), c.c) {
+            (Some(segment), Some(character)) if segment.quoting == c.quoting => {
+                segment.text.push(character)
+            }
+            _ => segments.push(Segment {
+                text: c.c.map(String::from).unwrap_or_default(),
+                quoting: c.quoting,
+            }),
+        }
+    }
+    Word { segments }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The texts of the words an unquoted word expands to.
+    fn bare(text: &str) -> Vec<String> {
+        let words = expand(&Word::bare(text));
+        words
+            .iter()
+            .map(|word| word.segments.iter().map(|segment| segment.text.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn alternatives() {
+        assert_eq!(bare("a{b,c}d"), ["abd", "acd"]);
+        assert_eq!(bare("file{,.bak}"), ["file", "file.bak"]);
+        assert_eq!(bare("{a,b}{1,2}"), ["a1", "a2", "b1", "b2"]);
+        assert_eq!(bare("{a,{b,c}x,d}"), ["a", "bx", "cx", "d"]);
+        assert_eq!(bare("src/{bin,lib,tests}"), ["src/bin", "src/lib", "src/tests"]);
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(bare("{1..5}"), ["1", "2", "3", "4", "5"]);
+        assert_eq!(bare("{3..1}"), ["3", "2", "1"]);
+        assert_eq!(bare("{-1..1}"), ["-1", "0", "1"]);
+        assert_eq!(bare("{01..10..3}"), ["01", "04", "07", "10"]);
+        assert_eq!(bare("{010..8..-2}"), ["010", "008"]);
+        assert_eq!(bare("{a..z..5}"), ["a", "f", "k", "p", "u", "z"]);
+        assert_eq!(bare("{c..a}"), ["c", "b", "a"]);
+        assert_eq!(bare("{1..3..0}"), ["1", "2", "3"]);
+        assert_eq!(bare("x{1..2}{a..b}"), ["x1a", "x1b", "x2a", "x2b"]);
+    }
+
+    #[test]
+    fn not_expressions() {
+        for text in ["{}", "{a}", "{a,b", "a,b}", "{a..3}", "{1..2..3..4}", "${a,b}", "$(echo {a,b})"] {
+            assert_eq!(bare(text), [text]);
+        }
+    }
+
+    #[test]
+    fn huge_sequences_stay() {
+        let full = "{-9223372036854775808..9223372036854775807}";
+        assert_eq!(bare(full), [full]);
+        assert_eq!(bare("{1..9999999999}"), ["{1..9999999999}"]);
+        let stepped = "{-9223372036854775808..9223372036854775807..9223372036854775807}";
+        assert_eq!(bare(stepped).len(), 3);
+    }
+
+    #[test]
+    fn quoting() {
+        let quoted = |quoting| Segment {
+            text: "{a,b}".to_string(),
+            quoting,
+        };
+        for quoting in [Quoting::Single, Quoting::Double] {
+            let word = Word {
+                segments: vec![quoted(quoting)],
+            };
+            assert_eq!(expand(&word), [word]);
+        }
+        // `{a,"b,c"}`: the quoted comma doesn't separate
+        let word = Word {
+            segments: vec![
+                Segment {
+                    text: "{a,".to_string(),
+                    quoting: Quoting::Bare,
+                },
+                Segment {
+                    text: "b,c".to_string(),
+                    quoting: Quoting::Double,
+                },
+                Segment {
+                    text: "}".to_string(),
+                    quoting: Quoting::Bare,
+                },
+            ],
+        };
+        let words = expand(&word);
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[1].segments[0].text, "b,c");
+        assert_eq!(words[1].segments[0].quoting, Quoting::Double);
+    }
+}
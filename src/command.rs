@@ -1,92 +1,279 @@
-use std::fmt::format;
-use std::fs::{self, File};
-use std::io::{self, Write};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::os::fd::RawFd;
 use std::os::unix::process::CommandExt;
-use std::path::Path;
 use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-pub const builtin_commands: [&str; 6] = ["exit", "echo", "type", "pwd", "cd","clear"];
+use crate::arithmetic::Arithmetic;
+use crate::glob::GlobOptions;
+use crate::parser::is_name;
+use crate::shell::Shell;
+use crate::sys;
+use crate::tokenizer::Span;
+
+pub const BUILTIN_COMMANDS: [&str; 17] = [
+    "exit", "echo", "type", "pwd", "cd", "clear", "jobs", "fg", "bg", "disown", "export",
+    "unset", "readonly", "let", "shopt", "return", "shift",
+];
 
 #[derive(Debug)]
 pub struct CommandOutput {
     pub success: Option<String>,
     pub error: Option<String>,
+    pub status: i32, // exit status, 0 on success
 }
 
-#[derive(Debug)]
-pub enum RedirectCode {
-    One(String),
-    Two(String),
+// `[n]< file`, `[n]> file`, `[n]>> file` or `[n]>&m`. The target is a word
+// in the syntax tree and becomes a file name (or fd) once the command is expanded.
+#[derive(Debug, Clone)]
+pub enum Redirection<T = String> {
+    Input(RawFd, T),          // n< file, fd 0 when n is left out
+    OutputTruncate(RawFd, T), // n> file, fd 1 when n is left out (remove existing content and add new content)
+    OutputAppend(RawFd, T),   // n>> file (append new content to existing content)
+    Duplicate(RawFd, T),      // n>&m or n<&m makes n a copy of m, n>&- closes n
+    HereDoc(RawFd, T),        // n<<EOF and n<<< word: the target is the text to read
 }
 
-#[derive(Debug)]
-pub enum Redirection {
-    Input(String),                // < file
-    OutputTruncate(RedirectCode), // > file   (remove existing content and add new content)
-    OutputAppend(RedirectCode),   // >> file  (append new content to existing content)
+// Where a redirected descriptor ends up.
+pub enum RedirectTarget {
+    File(File),
+    Fd(RawFd), // another descriptor of the shell
+    Closed,
+}
+
+impl<T> Redirection<T> {
+    // The descriptor being redirected.
+    pub fn fd(&self) -> RawFd {
+        match self {
+            Redirection::Input(fd, _)
+            | Redirection::OutputTruncate(fd, _)
+            | Redirection::OutputAppend(fd, _)
+            | Redirection::Duplicate(fd, _)
+            | Redirection::HereDoc(fd, _) => *fd,
+        }
+    }
+
+    pub fn target(&self) -> &T {
+        match self {
+            Redirection::Input(_, target)
+            | Redirection::OutputTruncate(_, target)
+            | Redirection::OutputAppend(_, target)
+            | Redirection::Duplicate(_, target)
+            | Redirection::HereDoc(_, target) => target,
+        }
+    }
+
+    // The same redirection with its target converted by `f`.
+    pub fn map<U>(&self, f: impl FnOnce(&T) -> U) -> Redirection<U> {
+        match self {
+            Redirection::Input(fd, target) => Redirection::Input(*fd, f(target)),
+            Redirection::OutputTruncate(fd, target) => Redirection::OutputTruncate(*fd, f(target)),
+            Redirection::OutputAppend(fd, target) => Redirection::OutputAppend(*fd, f(target)),
+            Redirection::Duplicate(fd, target) => Redirection::Duplicate(*fd, f(target)),
+            Redirection::HereDoc(fd, target) => Redirection::HereDoc(*fd, f(target)),
+        }
+    }
 }
+
+impl Redirection {
+    // Opens the target file, or works out which descriptor to copy.
+    pub fn open(&self) -> Result<RedirectTarget, String> {
+        let (file, result) = match self {
+            Redirection::Input(_, file) => (file, File::open(file)),
+            Redirection::OutputTruncate(_, file) => (file, File::create(file)),
+            Redirection::OutputAppend(_, file) => (file, open_append(file)),
+            Redirection::HereDoc(_, text) => {
+                return here_document(text)
+                    .map(RedirectTarget::File)
+                    .map_err(|e| format!("here-document: {}\n", sys::error_message(&e)))
+            }
+            Redirection::Duplicate(_, target) if target == "-" => return Ok(RedirectTarget::Closed),
+            Redirection::Duplicate(_, target) => {
+                return match target.parse() {
+                    Ok(fd) => Ok(RedirectTarget::Fd(fd)),
+                    Err(_) => Err(format!("{}: ambiguous redirect\n", target)),
+                }
+            }
+        };
+        result
+            .map(RedirectTarget::File)
+            .map_err(|e| format!("{}: {}\n", file, sys::error_message(&e)))
+    }
+}
+
 #[derive(Debug)]
 pub struct Command {
     pub program: String,   // The name of the command/program
     pub args: Vec<String>, // The arguments passed to that program
-    pub redirection: Option<Redirection>,
+    pub redirections: Vec<Redirection>, // applied left to right
+    pub env: Vec<(String, String)>, // `NAME=value` given in front of the command
+    pub span: Span,                 // where the command name was typed
+}
+
+// Written the way it is typed, the fd only when it isn't the default one.
+impl<T: fmt::Display> fmt::Display for Redirection<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (default_fd, operator, target) = match self {
+            Redirection::Input(_, target) => (0, "<", target),
+            Redirection::OutputTruncate(_, target) => (1, ">", target),
+            Redirection::OutputAppend(_, target) => (1, ">>", target),
+            Redirection::Duplicate(fd, target) => return write!(f, "{}>&{}", fd, target),
+            // the body would span lines, show where it goes
+            Redirection::HereDoc(0, _) => return write!(f, "<< ..."),
+            Redirection::HereDoc(fd, _) => return write!(f, "{}<< ...", fd),
+        };
+        if self.fd() != default_fd {
+            write!(f, "{}", self.fd())?;
+        }
+        write!(f, "{} {}", operator, target)
+    }
 }
 
 impl Command {
-    pub fn execute(&self) -> CommandOutput {
+    pub fn execute(&self, shell: &mut Shell) -> CommandOutput {
         match self.program.as_str() {
-            "exit" => run_exit(&self.args),
+            "exit" => run_exit(&self.args, shell),
             "echo" => run_echo(&self.args),
-            "type" => run_type(&self.args),
+            "type" => run_type(&self.args, shell),
             "pwd" => run_pwd(),
-            "cd" => run_cd(&self.args),
+            "cd" => run_cd(&self.args, shell),
             "clear" =>run_clear(),
-            _ => {
-                if is_external_program(&self.program) {
-                    println!("here");
-                    run_external_programs(&self.program, &self.args)
-                } else {
-                    CommandOutput {
-                        success: None,
-                        error: Some(format!("{}: command not found\n", self.program)),
-                    }
-                }
-            }
+            "jobs" => run_jobs(shell),
+            "fg" => run_fg(&self.args, shell),
+            "bg" => run_bg(&self.args, shell),
+            "disown" => run_disown(&self.args, shell),
+            "export" | "readonly" => run_declare(&self.program, &self.args, shell),
+            "unset" => run_unset(&self.args, shell),
+            "let" => run_let(&self.args, shell),
+            "shopt" => run_shopt(&self.args, shell),
+            "return" => run_return(&self.args, shell),
+            "shift" => run_shift(&self.args, shell),
+            // external programs are spawned by the executor, see `exec_external`
+            _ => CommandOutput {
+                success: None,
+                error: Some(self.not_found(shell)),
+                status: 127,
+            },
+        }
+    }
+
+    // "name: command not found", followed by the input with the name underlined.
+    fn not_found(&self, shell: &Shell) -> String {
+        let mut message = format!("{}: command not found\n", self.program);
+        let typed = shell.source.get(self.span.start..self.span.end);
+        if typed.is_some_and(|typed| !typed.is_empty()) {
+            message.push_str(&self.span.underline(&shell.source));
+            message.push('\n');
         }
+        message
     }
+
+    pub fn is_builtin(&self) -> bool {
+        BUILTIN_COMMANDS.contains(&self.program.as_str())
+    }
+
+    // Replaces the current (forked) process with the external program.
+    // Only returns, with the exit status to use, when that is not possible:
+    // 127 when the program doesn't exist, 126 when it can't be executed.
+    pub fn exec_external(&self, shell: &Shell) -> i32 {
+        // a name with a slash is a path and skips the PATH lookup
+        let path = if self.program.contains('/') {
+            self.program.clone()
+        } else if let Some(path) = search_in_path(&self.program, shell) {
+            path
+        } else {
+            eprint!("{}", self.not_found(shell));
+            return 127;
+        };
+        let err = process::Command::new(path)
+            .arg0(&self.program)
+            .args(&self.args)
+            // only the exported variables, with the command's own assignments on top
+            .env_clear()
+            .envs(shell.variables.exported())
+            .envs(self.env.iter().map(|(name, value)| (name, value)))
+            .exec();
+        eprintln!("{}: {}", self.program, sys::error_message(&err));
+        if err.kind() == io::ErrorKind::NotFound {
+            127
+        } else {
+            126
+        }
+    }
+}
+
+fn open_append(file: &str) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(file)
+}
+
+// Puts the text in an anonymous temporary file, positioned at its start.
+// A file rather than a pipe, so a body of any size never blocks the shell.
+fn here_document(text: &str) -> io::Result<File> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let name = format!(
+        "rusty_shell-{}-{}",
+        sys::getpid(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    );
+    let path = std::env::temp_dir().join(name);
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    let _ = std::fs::remove_file(&path);
+    file.write_all(text.as_bytes())?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
 }
-fn run_exit(args: &Vec<String>) -> CommandOutput {
-    let exit_code_str = &args[0];
+
+fn run_exit(args: &[String], shell: &Shell) -> CommandOutput {
+    // a bare `exit` keeps the status of the last command
+    let Some(exit_code_str) = args.first() else {
+        process::exit(shell.last_status);
+    };
     if let Ok(exit_code) = exit_code_str.parse::<i32>() {
         process::exit(exit_code);
     }
     process::exit(1);
 }
-fn run_echo(args: &Vec<String>) -> CommandOutput {
+fn run_echo(args: &[String]) -> CommandOutput {
     CommandOutput {
         success: Some(format!("{}\n", args.join(" "))),
         error: None,
+        status: 0,
     }
 }
-fn run_type(args: &Vec<String>) -> CommandOutput {
+fn run_type(args: &[String], shell: &Shell) -> CommandOutput {
     let program = &args[0].as_str();
-    get_type(program)
+    get_type(program, shell)
 }
-fn get_type(program: &str) -> CommandOutput {
-    if builtin_commands.contains(&program) {
+fn get_type(program: &str, shell: &Shell) -> CommandOutput {
+    if let Some(body) = shell.functions.get(program) {
+        CommandOutput {
+            success: Some(format!("{} is a function\n{}() {}\n", program, program, body)),
+            error: None,
+            status: 0,
+        }
+    } else if BUILTIN_COMMANDS.contains(&program) {
         CommandOutput {
             success: Some(format!("{} is a shell builtin\n", program)),
             error: None,
+            status: 0,
         }
     } else {
-        match search_in_path(program) {
+        match search_in_path(program, shell) {
             Some(path) => CommandOutput {
                 success: Some(format!("{} is {}\n", program, path)),
                 error: None,
+                status: 0,
             },
             None => CommandOutput {
                 success: None,
                 error: Some(format!("{}: not found\n", program)),
+                status: 1,
             },
         }
     }
@@ -96,71 +283,47 @@ fn run_pwd() -> CommandOutput {
         Ok(path) => CommandOutput {
             success: Some(format!("{}\n", path.display())),
             error: None,
+            status: 0,
         },
         Err(e) => CommandOutput {
             success: None,
             error: Some(format!("Error getting current directory: {}\n", e)),
+            status: 1,
         },
     }
 }
 
-fn run_cd(args: &Vec<String>) -> CommandOutput {
-    let target_dir = if args.is_empty() || args[0] == "~" {
-        std::env::var("HOME").unwrap_or_else(|_| "/".to_string())
+// `cd dir`, or `cd` to go home. Keeps `PWD` and `OLDPWD`, which `~+` and `~-` expand to.
+fn run_cd(args: &[String], shell: &mut Shell) -> CommandOutput {
+    let target_dir = if args.is_empty() {
+        shell.variables.get("HOME").unwrap_or("/").to_string()
     } else {
         args[0].clone()
     };
 
-    if let Err(_) = std::env::set_current_dir(&target_dir) {
+    if std::env::set_current_dir(&target_dir).is_err() {
         return CommandOutput {
             success: None,
             error: Some(format!("cd: {}: No such file or directory\n", target_dir)),
+            status: 1,
         };
     }
 
-    CommandOutput {
-        success: None, // cd usually doesn't output anything on success
-        error: None,
-    }
-}
-
-fn run_external_programs(program: &str, args: &Vec<String>) -> CommandOutput {
-    get_external_program_output(program, args)
-}
-fn get_external_program_output(program: &str, args: &Vec<String>) -> CommandOutput {
-    match search_in_path(program) {
-        Some(path) => {
-            let output = process::Command::new(path)
-                .arg0(program)
-                .args(args)
-                .output()
-                .expect("failed to execute process");
-
-            let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-
-            CommandOutput {
-                success: if stdout.is_empty() {
-                    None
-                } else {
-                    Some(stdout)
-                },
-                error: if stderr.is_empty() {
-                    None
-                } else {
-                    Some(stderr)
-                },
-            }
+    // cd usually doesn't output anything on success
+    let mut errors = String::new();
+    let old = shell.variables.get("PWD").map(str::to_string);
+    let dir = std::env::current_dir().ok().map(|dir| dir.to_string_lossy().into_owned());
+    for (name, value) in [("OLDPWD", old), ("PWD", dir)] {
+        if let Some(Err(e)) = value.map(|value| shell.variables.set(name, value)) {
+            errors.push_str(&format!("cd: {}\n", e));
         }
-        None => CommandOutput {
-            success: None,
-            error: Some(format!("{}: command not found\n", program)),
-        },
     }
+    variable_errors(errors)
 }
-fn search_in_path(program: &str) -> Option<String> {
-    // Get the PATH environment variable
-    let env_path = std::env::var("PATH").unwrap_or_default();
+
+fn search_in_path(program: &str, shell: &Shell) -> Option<String> {
+    // Get the PATH shell variable
+    let env_path = shell.variables.get("PATH").unwrap_or_default();
     let directories = env_path.split(':').collect::<Vec<&str>>();
     for dir in directories {
         let full_path = format!("{}/{}", dir, program);
@@ -171,33 +334,300 @@ fn search_in_path(program: &str) -> Option<String> {
     }
     None
 }
-fn is_external_program(program: &str) -> bool {
-    match search_in_path(program) {
-        Some(_) => true,
-        None => false,
-    }
-}
 fn is_executable(path: &str) -> bool {
     #[cfg(unix)]
     {
         use std::os::unix::fs::PermissionsExt;
         let metadata = std::fs::metadata(path).ok().unwrap();
         let permissions = metadata.permissions();
-        return permissions.mode() & 0o111 != 0; // Check if any execute bit is set
+        permissions.mode() & 0o111 != 0 // Check if any execute bit is set
     }
 }
 
-fn needs_newline(output: &str) -> bool {
-    output
-        .chars()
-        .any(|c| !c.is_control() && !c.is_whitespace())
+fn run_clear() -> CommandOutput {
+    CommandOutput {
+        success: Some("\x1B[2J\x1B[1;1H".to_string()), // ANSI escape code to clear terminal
+        error: None,
+        status: 0,
+    }
 }
 
-fn run_clear() -> CommandOutput {
-    print!("\x1B[2J\x1B[1;1H"); // ANSI escape code to clear terminal
-    std::io::stdout().flush().unwrap();
+fn run_jobs(shell: &mut Shell) -> CommandOutput {
+    let mut lines = String::new();
+    for line in shell.jobs.list() {
+        lines.push_str(&line);
+        lines.push('\n');
+    }
+    CommandOutput {
+        success: if lines.is_empty() { None } else { Some(lines) },
+        error: None,
+        status: 0,
+    }
+}
+
+fn job_error(builtin: &str, message: &str) -> CommandOutput {
+    CommandOutput {
+        success: None,
+        error: Some(format!("{}: {}\n", builtin, message)),
+        status: 1,
+    }
+}
+
+fn run_fg(args: &[String], shell: &mut Shell) -> CommandOutput {
+    if !shell.job_control {
+        return job_error("fg", "no job control");
+    }
+    let id = match shell.jobs.resolve(args.first().map(String::as_str)) {
+        Ok(id) => id,
+        Err(e) => return job_error("fg", &e),
+    };
+    let Some(job) = shell.jobs.remove(id) else {
+        return job_error("fg", "no such job");
+    };
+    // announce the job before it takes over the terminal
+    let _ = sys::write_all(1, format!("{}\n", job.command).as_bytes());
+    let status = shell.run_foreground(job, true);
+    CommandOutput {
+        success: None,
+        error: None,
+        status,
+    }
+}
+
+fn run_bg(args: &[String], shell: &mut Shell) -> CommandOutput {
+    if !shell.job_control {
+        return job_error("bg", "no job control");
+    }
+    let id = match shell.jobs.resolve(args.first().map(String::as_str)) {
+        Ok(id) => id,
+        Err(e) => return job_error("bg", &e),
+    };
+    let Some(job) = shell.jobs.get_mut(id) else {
+        return job_error("bg", "no such job");
+    };
+    if let Err(e) = job.resume() {
+        return job_error("bg", &e.to_string());
+    }
+    CommandOutput {
+        success: Some(format!("[{}]+ {} &\n", id, job.command)),
+        error: None,
+        status: 0,
+    }
+}
+
+fn run_disown(args: &[String], shell: &mut Shell) -> CommandOutput {
+    let id = match shell.jobs.resolve(args.first().map(String::as_str)) {
+        Ok(id) => id,
+        Err(e) => return job_error("disown", &e),
+    };
+    shell.jobs.remove(id);
+    CommandOutput {
+        success: None,
+        error: None,
+        status: 0,
+    }
+}
+
+// `export` and `readonly`: give each `NAME` or `NAME=value` the attribute,
+// or list the variables that have it when there are no arguments.
+fn run_declare(builtin: &str, args: &[String], shell: &mut Shell) -> CommandOutput {
+    let export = builtin == "export";
+    if args.is_empty() {
+        let mut lines = String::new();
+        for (name, variable) in shell.variables.iter() {
+            if !(if export { variable.exported } else { variable.readonly }) {
+                continue;
+            }
+            let flags = match (variable.readonly, variable.exported) {
+                (true, true) => "-rx",
+                (true, false) => "-r",
+                _ => "-x",
+            };
+            match &variable.value {
+                Some(value) => lines.push_str(&format!("declare {} {}=\"{}\"\n", flags, name, escape(value))),
+                None => lines.push_str(&format!("declare {} {}\n", flags, name)),
+            }
+        }
+        return CommandOutput {
+            success: if lines.is_empty() { None } else { Some(lines) },
+            error: None,
+            status: 0,
+        };
+    }
+
+    let mut errors = String::new();
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if !is_name(name) {
+            errors.push_str(&format!("{}: '{}': not a valid identifier\n", builtin, arg));
+            continue;
+        }
+        if let Some(value) = value {
+            if let Err(e) = shell.variables.set(name, value.to_string()) {
+                errors.push_str(&format!("{}: {}\n", builtin, e));
+                continue;
+            }
+        }
+        if export {
+            shell.variables.export(name);
+        } else {
+            shell.variables.make_readonly(name);
+        }
+    }
+    variable_errors(errors)
+}
+
+fn run_unset(args: &[String], shell: &mut Shell) -> CommandOutput {
+    let mut errors = String::new();
+    // variables unless `-f` asks for functions, `-v` says so explicitly
+    let functions = args.first().is_some_and(|arg| arg == "-f");
+    for name in args.iter().filter(|arg| *arg != "-v" && *arg != "-f") {
+        if functions {
+            shell.functions.remove(name);
+        } else if let Err(e) = shell.variables.unset(name) {
+            errors.push_str(&format!("unset: {}\n", e));
+        }
+    }
+    variable_errors(errors)
+}
+
+// Leaves the running function with status `n`, or that of the last command.
+fn run_return(args: &[String], shell: &mut Shell) -> CommandOutput {
+    let error = |message: String| CommandOutput {
+        success: None,
+        error: Some(format!("return: {}\n", message)),
+        status: 2,
+    };
+    if shell.function_depth == 0 {
+        return error("can only `return' from a function".to_string());
+    }
+    let status = match args.first() {
+        Some(arg) => match arg.parse::<i64>() {
+            Ok(n) => n.rem_euclid(256) as i32,
+            Err(_) => return error(format!("{}: numeric argument required", arg)),
+        },
+        None => shell.last_status,
+    };
+    shell.returning = true;
+    CommandOutput {
+        success: None,
+        error: None,
+        status,
+    }
+}
+
+// Drops the first `n` arguments, 1 by default, so `$2` becomes `$1`.
+fn run_shift(args: &[String], shell: &mut Shell) -> CommandOutput {
+    let count = match args.first().map(|arg| arg.parse::<usize>()) {
+        None => 1,
+        Some(Ok(count)) => count,
+        Some(Err(_)) => {
+            return CommandOutput {
+                success: None,
+                error: Some(format!("shift: {}: numeric argument required\n", args[0])),
+                status: 1,
+            }
+        }
+    };
+    if count > shell.positional.len() {
+        // nothing is shifted, like in other shells
+        return CommandOutput {
+            success: None,
+            error: None,
+            status: 1,
+        };
+    }
+    shell.positional.drain(..count);
     CommandOutput {
         success: None,
         error: None,
+        status: 0,
     }
-}
\ No newline at end of file
+}
+
+// Evaluates each argument as an arithmetic expression. Succeeds when the
+// last one isn't 0.
+fn run_let(args: &[String], shell: &mut Shell) -> CommandOutput {
+    let mut value = Err("expression expected".to_string());
+    for arg in args {
+        value = Arithmetic::evaluate(shell, arg);
+        if value.is_err() {
+            break;
+        }
+    }
+    let value = match value {
+        Ok(value) => value,
+        Err(e) => {
+            return CommandOutput {
+                success: None,
+                error: Some(format!("let: {}\n", e)),
+                status: 1,
+            }
+        }
+    };
+    CommandOutput {
+        success: None,
+        error: None,
+        status: if value == 0 { 1 } else { 0 },
+    }
+}
+
+// `shopt -s name...` sets options, `shopt -u name...` unsets them. Without
+// a flag it shows them, all of them when no name is given, and succeeds when
+// they are all set.
+fn run_shopt(args: &[String], shell: &mut Shell) -> CommandOutput {
+    let (value, names) = match args.first().map(String::as_str) {
+        Some("-s") => (Some(true), &args[1..]),
+        Some("-u") => (Some(false), &args[1..]),
+        _ => (None, args),
+    };
+    let names: Vec<&str> = match names.is_empty() {
+        true => GlobOptions::NAMES.to_vec(),
+        false => names.iter().map(String::as_str).collect(),
+    };
+    let mut output = String::new();
+    let mut errors = String::new();
+    let mut all_set = true;
+    for name in names {
+        let Some(option) = shell.glob_options.get_mut(name) else {
+            errors.push_str(&format!("shopt: {}: invalid shell option name\n", name));
+            continue;
+        };
+        match value {
+            Some(value) => *option = value,
+            None => {
+                output.push_str(&format!("{:<16}{}\n", name, if *option { "on" } else { "off" }));
+                all_set &= *option;
+            }
+        }
+    }
+    CommandOutput {
+        success: if output.is_empty() { None } else { Some(output) },
+        status: if errors.is_empty() && all_set { 0 } else { 1 },
+        error: if errors.is_empty() { None } else { Some(errors) },
+    }
+}
+
+fn variable_errors(errors: String) -> CommandOutput {
+    let status = if errors.is_empty() { 0 } else { 1 };
+    CommandOutput {
+        success: None,
+        error: if errors.is_empty() { None } else { Some(errors) },
+        status,
+    }
+}
+
+// A value the way it is written between double quotes.
+fn escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        if "\\\"$`".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -1,89 +1,589 @@
-use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
 
 use crate::{
-    command::{CommandOutput, RedirectCode, Redirection},
-    parser::ParsedLine,
+    command::{Command, CommandOutput, RedirectTarget, Redirection},
+    expand::Expander,
+    jobs::Job,
+    pattern::Pattern,
+    parser::{
+        AndOr, CommandList, CommandNode, CompoundCommand, Connector, ExecMode, Pipeline,
+        SimpleCommand,
+    },
+    shell::Shell,
+    sys::{self, Fork},
+    tokenizer::Word,
 };
 pub struct Executor;
 
+// A pipeline stage: either a simple command the shell already expanded, or a
+// node the forked child expands and runs on its own.
+enum Stage<'a> {
+    Expanded(Command),
+    Node(&'a CommandNode),
+}
+
 impl Executor {
-    pub fn execute(parsed_line: ParsedLine) {
-        for cmd in parsed_line.pipeline.commands {
-            let output_type = cmd.execute();
-            Self::process_output(output_type, cmd.redirection);
+    pub fn execute(shell: &mut Shell, list: &CommandList) {
+        for item in &list.items {
+            if shell.abandoning() {
+                return;
+            }
+            match item.mode {
+                ExecMode::Foreground => Self::execute_and_or(shell, &item.and_or),
+                ExecMode::Background => Self::execute_background(shell, &item.and_or),
+            }
+        }
+    }
+
+    // Runs the pipelines left to right, skipping the ones `&&` and `||` rule
+    // out based on the status of the pipeline before them.
+    fn execute_and_or(shell: &mut Shell, and_or: &AndOr) {
+        Self::execute_pipeline(shell, &and_or.first);
+        for (connector, pipeline) in &and_or.rest {
+            let run = match connector {
+                Connector::And => shell.last_status == 0,
+                Connector::Or => shell.last_status != 0,
+            };
+            if run && !shell.abandoning() {
+                Self::execute_pipeline(shell, pipeline);
+            }
+        }
+    }
+
+    fn execute_pipeline(shell: &mut Shell, pipeline: &Pipeline) {
+        // process substitutions made while expanding belong to this pipeline
+        let substitutions = shell.substitution_fds.len();
+        let status = match pipeline.commands.as_slice() {
+            [CommandNode::Simple(simple)] => match Self::expand(shell, simple) {
+                Err(error) => {
+                    eprintln!("{}", error);
+                    1
+                }
+                // Ctrl-C in a command substitution
+                Ok(_) if shell.interrupted => 128 + libc::SIGINT,
+                // functions run inside the shell too, they can change its variables
+                Ok(cmd) if shell.functions.contains_key(&cmd.program) => {
+                    Self::with_redirections(&cmd.redirections, || Self::call_function(shell, &cmd))
+                }
+                // a lone builtin runs inside the shell so `cd` and `exit` affect it
+                Ok(cmd) if cmd.program.is_empty() || cmd.is_builtin() => {
+                    Self::execute_builtin(shell, &cmd)
+                }
+                Ok(cmd) => {
+                    Self::run_foreground(shell, vec![Stage::Expanded(cmd)], pipeline.to_string())
+                }
+            },
+            // so do compound commands, except for the subshell that must not touch our state
+            [CommandNode::Compound(compound, redirections)]
+                if !matches!(compound, CompoundCommand::Subshell(_)) =>
+            {
+                Self::execute_redirected(shell, compound, redirections)
+            }
+            nodes => {
+                let stages = nodes.iter().map(Stage::Node).collect();
+                Self::run_foreground(shell, stages, pipeline.to_string())
+            }
+        };
+        shell.last_status = match (pipeline.negated, status) {
+            (false, status) => status,
+            (true, 0) => 1,
+            (true, _) => 0,
+        };
+        shell.finish_substitutions(substitutions);
+    }
+
+    fn run_foreground(shell: &mut Shell, stages: Vec<Stage>, command: String) -> i32 {
+        let pids = Self::spawn_pipeline(shell, stages, true);
+        match pids.first() {
+            Some(&pgid) => shell.run_foreground(Job::new(pgid, pids, command), false),
+            None => 1,
+        }
+    }
+
+    // Runs a compound command in the shell with its redirections.
+    fn execute_redirected(
+        shell: &mut Shell,
+        compound: &CompoundCommand,
+        redirections: &[Redirection<Word>],
+    ) -> i32 {
+        match Self::expand_redirections(shell, redirections) {
+            Ok(redirections) => Self::with_redirections(&redirections, || {
+                Self::execute_compound(shell, compound)
+            }),
+            Err(error) => {
+                eprintln!("{}", error);
+                1
+            }
         }
     }
-    fn process_output(output: CommandOutput, redirection: Option<Redirection>) {
-        match redirection {
-            Some(Redirection::OutputTruncate(RedirectCode::One(file))) => {
-                File::create(&file).expect("can't create file");
-                // redirect stdout only
-                if let Some(content) = output.success {
-                    std::fs::write(file, content).expect("Failed to write to file");
+
+    // Runs the function the command names, with its arguments as `$1`, `$2`,
+    // ... and its `NAME=value` prefixes set until it returns. The caller
+    // applies the command's redirections.
+    fn call_function(shell: &mut Shell, cmd: &Command) -> i32 {
+        let Some(body) = shell.functions.get(&cmd.program).cloned() else {
+            return 127;
+        };
+        let mut saved = Vec::new();
+        for (name, value) in &cmd.env {
+            saved.push((name, shell.variables.get(name).map(str::to_string)));
+            if let Err(error) = shell.variables.set(name, value.clone()) {
+                eprintln!("{}", error);
+                return 1;
+            }
+        }
+        let positional = std::mem::replace(&mut shell.positional, cmd.args.clone());
+        shell.function_depth += 1;
+        let status = match &*body {
+            // like any subshell, it runs in a forked copy of the shell
+            CommandNode::Compound(CompoundCommand::Subshell(_), _) => {
+                Self::run_foreground(shell, vec![Stage::Node(&body)], body.to_string())
+            }
+            CommandNode::Compound(compound, redirections) => {
+                Self::execute_redirected(shell, compound, redirections)
+            }
+            // the parser only takes a compound command as the body
+            CommandNode::Simple(_) => unreachable!("function body is a simple command"),
+        };
+        shell.function_depth -= 1;
+        shell.positional = positional;
+        shell.returning = false;
+        for (name, value) in saved.into_iter().rev() {
+            let _ = match value {
+                Some(value) => shell.variables.set(name, value),
+                None => shell.variables.unset(name),
+            };
+        }
+        status
+    }
+
+    fn execute_background(shell: &mut Shell, and_or: &AndOr) {
+        let pids = if and_or.rest.is_empty() && !and_or.first.negated {
+            let stages = and_or.first.commands.iter().map(Stage::Node).collect();
+            Self::spawn_pipeline(shell, stages, false)
+        } else {
+            // a whole `&&`/`||` chain runs in a forked copy of the shell
+            Self::spawn_subshell(shell, |shell| {
+                Self::execute_and_or(shell, and_or);
+                shell.last_status
+            })
+            .into_iter()
+            .collect()
+        };
+
+        if let (Some(&pgid), Some(&pid)) = (pids.first(), pids.last()) {
+            let id = shell.jobs.add(Job::new(pgid, pids, and_or.to_string()));
+            println!("[{}] {}", id, pid);
+        }
+        shell.last_status = 0;
+    }
+
+    fn execute_compound(shell: &mut Shell, compound: &CompoundCommand) -> i32 {
+        match compound {
+            // by the time a subshell runs the shell has already forked
+            CompoundCommand::BraceGroup(list) | CompoundCommand::Subshell(list) => {
+                Self::execute(shell, list);
+                shell.last_status
+            }
+            CompoundCommand::Function { name, body } => {
+                shell.functions.insert(name.clone(), body.clone());
+                0
+            }
+            CompoundCommand::Case { word, arms } => {
+                let value = match Expander::expand_word(shell, word) {
+                    Ok(value) => value,
+                    Err(error) => {
+                        eprintln!("{}", error);
+                        return 1;
+                    }
+                };
+                for arm in arms {
+                    for pattern in &arm.patterns {
+                        match Expander::expand_pattern(shell, pattern) {
+                            Ok(pattern) if Pattern::new(&pattern).matches(&value) => {
+                                if arm.body.items.is_empty() {
+                                    return 0;
+                                }
+                                Self::execute(shell, &arm.body);
+                                return shell.last_status;
+                            }
+                            Ok(_) => {}
+                            Err(error) => {
+                                eprintln!("{}", error);
+                                return 1;
+                            }
+                        }
+                    }
+                }
+                0
+            }
+            CompoundCommand::Arithmetic(expression) => match Expander::arithmetic(shell, expression) {
+                Ok(0) => 1,
+                Ok(_) => 0,
+                Err(error) => {
+                    eprintln!("{}", error);
+                    1
+                }
+            },
+            CompoundCommand::If {
+                branches,
+                else_body,
+            } => {
+                for (condition, body) in branches {
+                    Self::execute(shell, condition);
+                    if shell.last_status == 0 {
+                        Self::execute(shell, body);
+                        return shell.last_status;
+                    }
                 }
-                // print errors to console
-                if let Some(err) = output.error {
-                    eprint!("{}", err);
+                match else_body {
+                    Some(body) => {
+                        Self::execute(shell, body);
+                        shell.last_status
+                    }
+                    None => 0,
                 }
             }
-            Some(Redirection::OutputTruncate(RedirectCode::Two(file))) => {
-                File::create(&file).expect("can't create file");
-                // redirect stderr only
-                if let Some(err) = output.error {
-                    std::fs::write(file, err).expect("Failed to write error file");
+            CompoundCommand::While {
+                condition,
+                body,
+                until,
+            } => {
+                let mut status = 0;
+                while !shell.abandoning() {
+                    Self::execute(shell, condition);
+                    if shell.returning {
+                        return shell.last_status;
+                    }
+                    if (shell.last_status == 0) == *until || shell.interrupted {
+                        break;
+                    }
+                    Self::execute(shell, body);
+                    status = shell.last_status;
                 }
-                // print successful output to console
-                if let Some(content) = output.success {
-                    print!("{}", content);
+                status
+            }
+            CompoundCommand::For {
+                variable,
+                words,
+                body,
+            } => {
+                let words = match Expander::expand_words(shell, words) {
+                    Ok(words) => words,
+                    Err(error) => {
+                        eprintln!("{}", error);
+                        return 1;
+                    }
+                };
+                let mut status = 0;
+                for word in words {
+                    if shell.abandoning() {
+                        break;
+                    }
+                    if let Err(error) = shell.variables.set(variable, word) {
+                        eprintln!("{}", error);
+                        return 1;
+                    }
+                    Self::execute(shell, body);
+                    status = shell.last_status;
                 }
+                status
             }
-            Some(Redirection::OutputAppend(RedirectCode::One(file))) => {
-                use std::io::Write;
+        }
+    }
+
+    // Turns the words of a simple command into the command to run.
+    fn expand(shell: &mut Shell, simple: &SimpleCommand) -> Result<Command, String> {
+        shell.substitution_status = None;
+        let mut words = Expander::expand_words(shell, &simple.words)?.into_iter();
+        let redirections = Self::expand_redirections(shell, &simple.redirections)?;
+        let env = if simple.words.is_empty() {
+            // without a command the assignments are set one after the other,
+            // so that in `a=1 b=$a` the value of `b` sees the new `a`
+            for (name, value) in &simple.assignments {
+                let value = Expander::expand_assignment(shell, value)?;
+                shell.variables.set(name, value)?;
+            }
+            Vec::new()
+        } else {
+            simple
+                .assignments
+                .iter()
+                .map(|(name, value)| Ok((name.clone(), Expander::expand_assignment(shell, value)?)))
+                .collect::<Result<_, String>>()?
+        };
+        Ok(Command {
+            program: words.next().unwrap_or_default(),
+            args: words.collect(),
+            redirections,
+            env,
+            span: simple.span,
+        })
+    }
 
-                let mut f = std::fs::OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(&file)
-                    .expect("can't open file for append");
+    // Spawns every stage at once, each one reading the previous stage's
+    // stdout through a pipe, and returns their pids in pipeline order.
+    // External programs inherit the terminal unless redirected or piped.
+    // Under job control the stages share a process group led by the first one.
+    fn spawn_pipeline(shell: &mut Shell, stages: Vec<Stage>, foreground: bool) -> Vec<libc::pid_t> {
+        let last = stages.len() - 1;
+        let mut pids = Vec::new();
+        let mut pgid = 0;
+        let mut stdin: Option<OwnedFd> = None;
 
-                if let Some(content) = output.success {
-                    f.write_all(content.as_bytes())
-                        .expect("failed to append to file");
+        for (i, stage) in stages.into_iter().enumerate() {
+            let (reader, writer) = if i < last {
+                match sys::pipe() {
+                    Ok((reader, writer)) => (Some(reader), Some(writer)),
+                    Err(e) => {
+                        eprintln!("pipe: {}", e);
+                        break;
+                    }
                 }
-                if let Some(err) = output.error {
-                    eprint!("{}", err);
+            } else {
+                (None, None)
+            };
+
+            match sys::fork() {
+                Ok(Fork::Child) => {
+                    drop(reader);
+                    shell.enter_job(pgid, foreground);
+                    // pipelines inside this stage belong to its job, not to new ones
+                    shell.job_control = false;
+                    Self::run_stage(shell, stage, stdin, writer);
+                }
+                Ok(Fork::Parent(pid)) => {
+                    if pgid == 0 {
+                        pgid = pid;
+                    }
+                    // also done by the child, whichever runs first wins the race
+                    if shell.job_control {
+                        let _ = sys::setpgid(pid, pgid);
+                    }
+                    pids.push(pid);
+                }
+                Err(e) => {
+                    eprintln!("fork: {}", e);
+                    break;
                 }
             }
+            // the parent keeps only the read end, for the next stage
+            stdin = reader;
+        }
+        pids
+    }
 
-            Some(Redirection::OutputAppend(RedirectCode::Two(file))) => {
-                use std::io::Write;
+    // Forks a copy of the shell that runs `body` in its own background
+    // process group and exits with the status it returns.
+    fn spawn_subshell(
+        shell: &mut Shell,
+        body: impl FnOnce(&mut Shell) -> i32,
+    ) -> Option<libc::pid_t> {
+        match sys::fork() {
+            Ok(Fork::Child) => {
+                shell.enter_job(0, false);
+                shell.job_control = false;
+                let status = body(shell);
+                sys::exit(status);
+            }
+            Ok(Fork::Parent(pid)) => {
+                if shell.job_control {
+                    let _ = sys::setpgid(pid, pid);
+                }
+                Some(pid)
+            }
+            Err(e) => {
+                eprintln!("fork: {}", e);
+                None
+            }
+        }
+    }
 
-                let mut f = std::fs::OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(&file)
-                    .expect("can't open file for append");
+    // Body of a forked pipeline stage: wires up stdin/stdout, then the stage's
+    // own redirections on top of them, and never returns.
+    fn run_stage(
+        shell: &mut Shell,
+        stage: Stage,
+        stdin: Option<OwnedFd>,
+        stdout: Option<OwnedFd>,
+    ) -> ! {
+        for (fd, target) in [(stdin, 0), (stdout, 1)] {
+            if let Some(fd) = fd {
+                if let Err(e) = sys::dup2(fd.as_raw_fd(), target) {
+                    eprintln!("dup2: {}", e);
+                    sys::exit(1);
+                }
+            }
+        }
 
-                if let Some(err) = output.error {
-                    f.write_all(err.as_bytes())
-                        .expect("failed to append error to file");
+        let cmd = match stage {
+            Stage::Expanded(cmd) => cmd,
+            Stage::Node(CommandNode::Simple(simple)) => match Self::expand(shell, simple) {
+                Ok(cmd) => cmd,
+                Err(error) => {
+                    eprintln!("{}", error);
+                    sys::exit(1);
                 }
-                if let Some(content) = output.success {
-                    print!("{}", content);
+            },
+            Stage::Node(CommandNode::Compound(compound, redirections)) => {
+                let redirections = match Self::expand_redirections(shell, redirections) {
+                    Ok(redirections) => redirections,
+                    Err(error) => {
+                        eprintln!("{}", error);
+                        sys::exit(1);
+                    }
+                };
+                if let Err(error) = Self::redirect(&redirections) {
+                    eprint!("{}", error);
+                    sys::exit(1);
                 }
+                sys::exit(Self::execute_compound(shell, compound));
             }
-            None => {
-                // no redirection: print both
-                if let Some(content) = output.success {
-                    print!("{}", content);
+        };
+
+        if let Err(error) = Self::redirect(&cmd.redirections) {
+            eprint!("{}", error);
+            sys::exit(1);
+        }
+        if cmd.program.is_empty() {
+            sys::exit(shell.substitution_status.unwrap_or(0));
+        }
+        if shell.functions.contains_key(&cmd.program) {
+            sys::exit(Self::call_function(shell, &cmd));
+        }
+        if cmd.is_builtin() {
+            sys::exit(Self::print_output(&cmd.program, cmd.execute(shell)));
+        }
+        sys::exit(cmd.exec_external(shell))
+    }
+
+    // Runs a builtin inside the shell. Also handles commands made only of
+    // assignments and redirections, and those whose words expanded to nothing
+    // like `a=1 $unset`, whose assignments are set here.
+    fn execute_builtin(shell: &mut Shell, cmd: &Command) -> i32 {
+        Self::with_redirections(&cmd.redirections, || {
+            if cmd.program.is_empty() {
+                for (name, value) in &cmd.env {
+                    if let Err(error) = shell.variables.set(name, value.clone()) {
+                        eprintln!("{}", error);
+                        return 1;
+                    }
                 }
-                if let Some(err) = output.error {
-                    eprint!("{}", err);
+                // `x=$(cmd)` has the status of cmd
+                return shell.substitution_status.unwrap_or(0);
+            }
+            Self::print_output(&cmd.program, cmd.execute(shell))
+        })
+    }
+
+    // Runs `body` with the redirections applied to the shell's own
+    // descriptors, restoring them afterwards.
+    fn with_redirections(redirections: &[Redirection], body: impl FnOnce() -> i32) -> i32 {
+        let saved = match Self::redirect(redirections) {
+            Ok(saved) => saved,
+            Err(error) => {
+                eprint!("{}", error);
+                return 1;
+            }
+        };
+        let status = body();
+        Self::restore(saved);
+        status
+    }
+
+    // Opens each redirection target in turn and installs it as the descriptor
+    // it redirects in the current process. Returns copies of the descriptors
+    // it replaced, None for those that weren't open; when a redirection fails
+    // the earlier ones are undone.
+    fn redirect(redirections: &[Redirection]) -> Result<Vec<(RawFd, Option<OwnedFd>)>, String> {
+        let mut saved = Vec::new();
+        for redirection in redirections {
+            match Self::redirect_one(redirection) {
+                Ok(replaced) => saved.push(replaced),
+                Err(error) => {
+                    Self::restore(saved);
+                    return Err(error);
                 }
             }
-            _ => {
-                panic!("to be handeled")
+        }
+        Ok(saved)
+    }
+
+    fn redirect_one(redirection: &Redirection) -> Result<(RawFd, Option<OwnedFd>), String> {
+        let fd = redirection.fd();
+        // anything still buffered belongs to the old descriptor
+        let _ = io::stdout().flush();
+        // fails with EBADF when `fd` isn't open, then there is nothing to save
+        let saved = sys::dup(fd).ok();
+
+        match redirection.open()? {
+            RedirectTarget::File(file) => {
+                let mut file = OwnedFd::from(file);
+                if file.as_raw_fd() == fd {
+                    // opened right on the free descriptor, but close-on-exec: move it
+                    // away so that dup2 installs a copy programs inherit
+                    file = sys::dup(fd).map_err(|e| format!("dup: {}\n", e))?;
+                }
+                sys::dup2(file.as_raw_fd(), fd).map_err(|e| format!("dup2: {}\n", e))?;
+            }
+            RedirectTarget::Fd(source) => {
+                // also checks that `source` is open when it is `fd` itself
+                sys::dup2(source, fd)
+                    .map_err(|e| format!("{}: {}\n", source, sys::error_message(&e)))?;
+            }
+            // closing a descriptor that isn't open is not an error
+            RedirectTarget::Closed => {
+                let _ = sys::close(fd);
             }
         }
+        Ok((fd, saved))
+    }
+
+    // Gives the shell its own descriptors back, newest redirection first.
+    fn restore(saved: Vec<(RawFd, Option<OwnedFd>)>) {
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        for (fd, saved) in saved.into_iter().rev() {
+            let result = match saved {
+                Some(saved) => sys::dup2(saved.as_raw_fd(), fd),
+                None => sys::close(fd),
+            };
+            if let Err(e) = result {
+                eprintln!("restore {}: {}", fd, e);
+            }
+        }
+    }
+
+    // Expands the targets of the redirections into file names.
+    fn expand_redirections(
+        shell: &mut Shell,
+        redirections: &[Redirection<Word>],
+    ) -> Result<Vec<Redirection>, String> {
+        redirections
+            .iter()
+            .map(|redirection| {
+                let target = Expander::expand_word(shell, redirection.target())?;
+                Ok(redirection.map(|_| target))
+            })
+            .collect()
+    }
+
+    // Prints the builtin's output and returns its exit status. Output that
+    // can't be written, to a full disk or a closed pipe, fails the builtin.
+    fn print_output(builtin: &str, output: CommandOutput) -> i32 {
+        let mut status = output.status;
+        if let Some(content) = output.success {
+            let written = io::stdout().flush().and_then(|_| sys::write_all(1, content.as_bytes()));
+            if let Err(err) = written {
+                let message = format!("{}: write error: {}\n", builtin, sys::error_message(&err));
+                let _ = io::stderr().write_all(message.as_bytes());
+                status = 1;
+            }
+        }
+        if let Some(err) = output.error {
+            let _ = io::stderr().write_all(err.as_bytes());
+        }
+        status
     }
 }
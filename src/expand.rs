@@ -0,0 +1,774 @@
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::AsRawFd;
+
+use crate::{
+    arithmetic::Arithmetic,
+    brace,
+    executor::Executor,
+    glob,
+    parser::{is_name, CommandList, Parser},
+    pattern::{self, Pattern},
+    shell::Shell,
+    sys::{self, Fork, WaitStatus},
+    tokenizer::{substitution_len, Quoting, Segment, Tokenizer, Word},
+};
+
+// Characters that separate fields in the result of an unquoted expansion.
+const IFS: &str = " \t\n";
+
+// Word expansion: turns the words of the syntax tree into the strings
+// commands are run with.
+pub struct Expander;
+
+// A piece of an expanded word.
+struct Part {
+    text: String,
+    quoted: bool, // its `*`, `?` and `[` only match themselves
+    split: bool,  // the result of an unquoted expansion, split into fields
+}
+
+// A field being built from the parts of a word: its text, and the same text as a
+// pattern once an unquoted part brings a `*`, `?` or `[` into it.
+#[derive(Default)]
+struct Field {
+    text: String,
+    pattern: String,
+    glob: bool,
+}
+
+impl Expander {
+    // Expands the words into fields, once their braces made more words of them
+    // and their tildes were replaced. The result of an unquoted expansion is
+    // split on whitespace and disappears when empty; quoted text never is.
+    // A field with an unquoted `*`, `?` or `[` becomes the files it matches.
+    // Fails on an expansion the command can't run with, like `${a b}`.
+    pub fn expand_words(shell: &mut Shell, words: &[Word]) -> Result<Vec<String>, String> {
+        let mut fields = Vec::new();
+        for word in words.iter().flat_map(brace::expand) {
+            let word = Self::tilde(shell, &word, false);
+            let parts = Self::expand_parts(shell, &word)?;
+            for field in Self::split(parts) {
+                if !field.glob {
+                    fields.push(field.text);
+                    continue;
+                }
+                let matches = glob::expand(&field.pattern, &shell.glob_options);
+                let options = &shell.glob_options;
+                if !matches.is_empty() {
+                    fields.extend(matches);
+                } else if options.failglob {
+                    return Err(format!("no match: {}", field.text));
+                } else if !options.nullglob {
+                    fields.push(field.text);
+                }
+            }
+        }
+        Ok(fields)
+    }
+
+    // Expands a word that stays a single string, like the target of a redirection.
+    pub fn expand_word(shell: &mut Shell, word: &Word) -> Result<String, String> {
+        let word = Self::tilde(shell, word, false);
+        let parts = Self::expand_parts(shell, &word)?;
+        Ok(parts.into_iter().map(|part| part.text).collect())
+    }
+
+    // Expands the value of an assignment, where a `~` after a `:` is replaced
+    // too, as in `PATH=~/bin:~/.cargo/bin:$PATH`.
+    pub fn expand_assignment(shell: &mut Shell, word: &Word) -> Result<String, String> {
+        let word = Self::tilde(shell, word, true);
+        let parts = Self::expand_parts(shell, &word)?;
+        Ok(parts.into_iter().map(|part| part.text).collect())
+    }
+
+    // Replaces the unquoted `~` starting the word, and in an assignment each
+    // one following a `:`, with the directory it names: `~` is `$HOME`, `~+`
+    // is `$PWD`, `~-` is `$OLDPWD` and `~name` the home of that user. The name
+    // runs to the next `/` (or `:`) and can't be quoted; a tilde naming
+    // nothing known stays. The directory becomes quoted text, never split or
+    // globbed.
+    fn tilde(shell: &Shell, word: &Word, assignment: bool) -> Word {
+        let last = word.segments.len().saturating_sub(1);
+        let mut segments = Vec::new();
+        for (index, segment) in word.segments.iter().enumerate() {
+            if segment.quoting != Quoting::Bare {
+                segments.push(segment.clone());
+                continue;
+            }
+            let text = &segment.text;
+            let mut literal = String::new();
+            let mut i = 0;
+            while let Some(c) = text[i..].chars().next() {
+                let rest = &text[i..];
+                let opens = ["$(", "${", "`", "<(", ">("].iter().any(|open| rest.starts_with(open));
+                if let Some(len) = opens.then(|| substitution_len(rest, false)).flatten() {
+                    // a `:~` in a substitution is the substitution's
+                    literal.push_str(&rest[..len]);
+                    i += len;
+                    continue;
+                }
+                let start = (index == 0 && i == 0) || (assignment && literal.ends_with(':'));
+                if c == '~' && start {
+                    let name = rest[1..].split(|c| c == '/' || (assignment && c == ':')).next();
+                    let name = name.unwrap_or_default();
+                    // the name must end in this segment, unless it ends the word
+                    let ended = 1 + name.len() < rest.len() || index == last;
+                    if let Some(dir) = home(shell, name).filter(|_| ended) {
+                        if !literal.is_empty() {
+                            segments.push(Segment {
+                                text: std::mem::take(&mut literal),
+                                quoting: Quoting::Bare,
+                            });
+                        }
+                        segments.push(Segment {
+                            text: dir,
+                            quoting: Quoting::Single,
+                        });
+                        i += 1 + name.len();
+                        continue;
+                    }
+                }
+                literal.push(c);
+                i += c.len_utf8();
+            }
+            if !literal.is_empty() {
+                segments.push(Segment {
+                    text: literal,
+                    quoting: Quoting::Bare,
+                });
+            }
+        }
+        Word { segments }
+    }
+
+    // Expands a `case` pattern, where quoted text only matches itself.
+    pub fn expand_pattern(shell: &mut Shell, word: &Word) -> Result<String, String> {
+        let word = Self::tilde(shell, word, false);
+        let parts = Self::expand_parts(shell, &word)?;
+        Ok(parts
+            .into_iter()
+            .map(|part| if part.quoted { pattern::escape(&part.text) } else { part.text })
+            .collect())
+    }
+
+    // The word as pieces of text, each with how it was quoted.
+    fn expand_parts(shell: &mut Shell, word: &Word) -> Result<Vec<Part>, String> {
+        let mut parts = Vec::new();
+        for segment in &word.segments {
+            match segment.quoting {
+                Quoting::Single => parts.push(Part {
+                    text: segment.text.clone(),
+                    quoted: true,
+                    split: false,
+                }),
+                Quoting::Double => Self::substitute(shell, &segment.text, false, &mut parts)?,
+                Quoting::Bare => Self::substitute(shell, &segment.text, true, &mut parts)?,
+            }
+        }
+        Ok(parts)
+    }
+
+    // Replaces the parameters (`$?`, `$NAME`, `${...}`), `$(...)` and
+    // `` `...` `` in the text, and `<(...)` and `>(...)` when it is unquoted,
+    // and appends the result to `parts`. Only the substituted text can be split.
+    fn substitute(
+        shell: &mut Shell,
+        text: &str,
+        split: bool,
+        parts: &mut Vec<Part>,
+    ) -> Result<(), String> {
+        let quoted = !split;
+        let start = parts.len();
+        let mut arguments = false;
+        let mut literal = String::new();
+        let mut i = 0;
+        while let Some(c) = text[i..].chars().next() {
+            let rest = &text[i..];
+            let process = split && (rest.starts_with("<(") || rest.starts_with(">("));
+            let name = if c == '$' { parameter_name(&rest[1..]) } else { "" };
+            let (expanded, len) = if quoted && (name == "@" || rest.starts_with("${@}")) {
+                arguments = true;
+                (Self::arguments(shell), if name == "@" { 2 } else { 4 })
+            } else if !name.is_empty() {
+                let value = Self::parameter(shell, name).unwrap_or_default();
+                (vec![Part { text: value, quoted, split }], 1 + name.len())
+            } else if let Some(braced) = rest.strip_prefix("${") {
+                let (len, inner) = match substitution_len(rest, !split) {
+                    Some(len) => (len, &braced[..len - 3]),
+                    None => (rest.len(), braced),
+                };
+                (Self::braced(shell, inner, split)?, len)
+            } else if rest.starts_with("$(") || c == '`' || process {
+                let open = if c == '`' { 1 } else { 2 };
+                // an unclosed one runs to the end of the word
+                let (len, command) = match substitution_len(rest, !split) {
+                    Some(len) => (len, &rest[open..len - 1]),
+                    None => (rest.len(), &rest[open..]),
+                };
+                let expanded = match c {
+                    '`' => Self::command_substitution(shell, &unescape_backquoted(command)),
+                    '$' if command.len() > 1 && command.starts_with('(') && command.ends_with(')') => {
+                        Self::arithmetic(shell, &command[1..command.len() - 1])?.to_string()
+                    }
+                    '$' => Self::command_substitution(shell, command),
+                    _ => Self::process_substitution(shell, command, c == '<'),
+                };
+                (vec![Part { text: expanded, quoted, split }], len)
+            } else {
+                literal.push(c);
+                i += c.len_utf8();
+                continue;
+            };
+            if !literal.is_empty() {
+                let text = std::mem::take(&mut literal);
+                parts.push(Part { text, quoted, split: false });
+            }
+            parts.extend(expanded);
+            i += len;
+        }
+        if !literal.is_empty() || (quoted && parts.len() == start && !arguments) {
+            // quoted text makes a part even when empty, so `""` is still an
+            // argument, but `"$@"` without arguments is none
+            parts.push(Part { text: literal, quoted, split: false });
+        }
+        Ok(())
+    }
+
+    // The value of a special parameter, a positional one or a variable, None
+    // when it is unset. `$@` and `$*` join the arguments with spaces.
+    fn parameter(shell: &Shell, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(shell.last_status.to_string()),
+            "#" => Some(shell.positional.len().to_string()),
+            "@" | "*" => Some(shell.positional.join(" ")),
+            "0" => std::env::args().next(),
+            _ if name.starts_with(|c: char| c.is_ascii_digit()) => {
+                let index = name.parse::<usize>().ok()?;
+                shell.positional.get(index.checked_sub(1)?).cloned()
+            }
+            _ => shell.variables.get(name).map(str::to_string),
+        }
+    }
+
+    // `"$@"`: each argument quoted and in a field of its own.
+    fn arguments(shell: &Shell) -> Vec<Part> {
+        let mut parts = Vec::new();
+        for (i, argument) in shell.positional.iter().enumerate() {
+            if i > 0 {
+                // ends the field of the argument before
+                parts.push(Part { text: " ".to_string(), quoted: false, split: true });
+            }
+            parts.push(Part { text: argument.clone(), quoted: true, split: false });
+        }
+        parts
+    }
+
+    // Expands `${...}`, `inner` being the text between the braces: a
+    // parameter, its length with `#`, or a parameter and an operator.
+    fn braced(shell: &mut Shell, inner: &str, split: bool) -> Result<Vec<Part>, String> {
+        let bad = || format!("${{{}}}: bad substitution", inner);
+        let quoted = !split;
+        if let Some(name) = inner.strip_prefix('#').filter(|name| parameter_name(name) == *name) {
+            if !name.is_empty() {
+                let value = Self::parameter(shell, name).unwrap_or_default();
+                let text = value.chars().count().to_string();
+                return Ok(vec![Part { text, quoted, split }]);
+            }
+        }
+        // `${10}` is the tenth argument, where `$10` is `${1}0`
+        let digits = inner.find(|c: char| !c.is_ascii_digit()).unwrap_or(inner.len());
+        let name = if digits > 0 { &inner[..digits] } else { parameter_name(inner) };
+        if name.is_empty() {
+            return Err(bad());
+        }
+        let value = Self::parameter(shell, name);
+        let (operator, operand) = operator(&inner[name.len()..]).ok_or_else(bad)?;
+
+        let value = match operator {
+            "" => value.unwrap_or_default(),
+            "-" | ":-" | "=" | ":=" | "?" | ":?" | "+" | ":+" => {
+                // with the colon an empty value counts as unset
+                let set = match operator.starts_with(':') {
+                    true => value.as_ref().is_some_and(|value| !value.is_empty()),
+                    false => value.is_some(),
+                };
+                match (operator.trim_start_matches(':'), set) {
+                    ("-", false) | ("+", true) => {
+                        let parts = Self::operand(shell, operand, quoted)?;
+                        let parts = parts.into_iter().map(|part| Part {
+                            split: split && !part.quoted,
+                            quoted: quoted || part.quoted,
+                            text: part.text,
+                        });
+                        return Ok(parts.collect());
+                    }
+                    ("=", false) => {
+                        let value = Self::operand_text(shell, operand, quoted)?;
+                        if !is_name(name) {
+                            return Err(format!("${}: cannot assign in this way", name));
+                        }
+                        shell.variables.set(name, value.clone())?;
+                        value
+                    }
+                    ("?", false) => {
+                        let message = match Self::operand_text(shell, operand, quoted)? {
+                            message if message.is_empty() => "parameter null or not set".to_string(),
+                            message => message,
+                        };
+                        return Err(format!("{}: {}", name, message));
+                    }
+                    ("+", false) => String::new(),
+                    _ => value.unwrap_or_default(),
+                }
+            }
+            "#" | "##" | "%" | "%%" => {
+                let value = value.unwrap_or_default();
+                let pattern = Pattern::new(&Self::operand_pattern(shell, operand, quoted)?);
+                // where to cut, in the order to try: the shortest match first,
+                // or the longest one with `##` and `%%`
+                let mut cuts: Vec<usize> = boundaries(&value).collect();
+                if (operator.len() == 2) != operator.starts_with('%') {
+                    cuts.reverse();
+                }
+                let removed = cuts.into_iter().find_map(|at| match operator.starts_with('#') {
+                    true => pattern.matches(&value[..at]).then(|| value[at..].to_string()),
+                    false => pattern.matches(&value[at..]).then(|| value[..at].to_string()),
+                });
+                removed.unwrap_or(value)
+            }
+            "/" | "//" | "/#" | "/%" => {
+                let value = value.unwrap_or_default();
+                let (pattern, replacement) = split_operand(operand, '/', quoted);
+                let pattern = Self::operand_pattern(shell, pattern, quoted)?;
+                let replacement = match replacement {
+                    Some(replacement) => Self::operand_text(shell, replacement, quoted)?,
+                    None => String::new(),
+                };
+                match pattern.is_empty() {
+                    true => value,
+                    false => replace(&value, &Pattern::new(&pattern), &replacement, operator),
+                }
+            }
+            ":" => {
+                let value = value.unwrap_or_default();
+                let (offset, length) = split_operand(operand, ':', quoted);
+                let offset = Self::arithmetic(shell, offset)?;
+                let length = match length {
+                    Some(length) => Some(Self::arithmetic(shell, length)?),
+                    None => None,
+                };
+                substring(&value, offset, length)
+                    .ok_or_else(|| format!("{}: substring expression < 0", length.unwrap_or(0)))?
+            }
+            _ => {
+                // `^`, `^^`, `,` or `,,`: each character the pattern matches
+                // changes case, only the first one for a single `^` or `,`
+                let value = value.unwrap_or_default();
+                let pattern = match operand {
+                    "" => Pattern::new("?"),
+                    _ => Pattern::new(&Self::operand_pattern(shell, operand, quoted)?),
+                };
+                let mut result = String::new();
+                for (i, c) in value.chars().enumerate() {
+                    if (i == 0 || operator.len() == 2) && pattern.matches(c.encode_utf8(&mut [0; 4])) {
+                        match operator.starts_with('^') {
+                            true => result.extend(c.to_uppercase()),
+                            false => result.extend(c.to_lowercase()),
+                        }
+                    } else {
+                        result.push(c);
+                    }
+                }
+                result
+            }
+        };
+        Ok(vec![Part { text: value, quoted, split }])
+    }
+
+    // Expands the word of an operator into pieces; what isn't quoted inside
+    // the braces counts as unquoted.
+    fn operand(shell: &mut Shell, text: &str, quoted: bool) -> Result<Vec<Part>, String> {
+        let mut word = Tokenizer::operand(text, quoted);
+        if !quoted {
+            word = Self::tilde(shell, &word, false);
+        }
+        Self::expand_parts(shell, &word)
+    }
+
+    fn operand_text(shell: &mut Shell, text: &str, quoted: bool) -> Result<String, String> {
+        let parts = Self::operand(shell, text, quoted)?;
+        Ok(parts.into_iter().map(|part| part.text).collect())
+    }
+
+    // The word of an operator as a pattern, where quoted text only matches itself.
+    fn operand_pattern(shell: &mut Shell, text: &str, quoted: bool) -> Result<String, String> {
+        let parts = Self::operand(shell, text, quoted)?;
+        Ok(parts
+            .into_iter()
+            .map(|part| if part.quoted { pattern::escape(&part.text) } else { part.text })
+            .collect())
+    }
+
+    // Evaluates the text of `$((...))`, `((...))` or a `${name:offset:length}`
+    // number, after expanding it the way double-quoted text is.
+    pub fn arithmetic(shell: &mut Shell, text: &str) -> Result<i64, String> {
+        let expression = Self::operand_text(shell, text, true)?;
+        Arithmetic::evaluate(shell, &expression)
+    }
+
+    // Runs the command line in a forked copy of the shell and returns what it
+    // printed, without the trailing newlines.
+    fn command_substitution(shell: &mut Shell, command: &str) -> String {
+        let Some(list) = Self::parse(shell, command) else {
+            return String::new();
+        };
+        let (reader, writer) = match sys::pipe() {
+            Ok(pipe) => pipe,
+            Err(e) => {
+                eprintln!("pipe: {}", e);
+                return String::new();
+            }
+        };
+        let pid = match sys::fork() {
+            Ok(Fork::Child) => {
+                drop(reader);
+                // part of the job being expanded: stays in the shell's process group
+                shell.job_control = false;
+                shell.enter_job(0, false);
+                shell.source = command.to_string();
+                if let Err(e) = sys::dup2(writer.as_raw_fd(), 1) {
+                    eprintln!("dup2: {}", e);
+                    sys::exit(1);
+                }
+                drop(writer);
+                Executor::execute(shell, &list);
+                sys::exit(shell.last_status);
+            }
+            Ok(Fork::Parent(pid)) => pid,
+            Err(e) => {
+                eprintln!("fork: {}", e);
+                return String::new();
+            }
+        };
+
+        // read everything before waiting, the child blocks once the pipe is full
+        drop(writer);
+        let mut output = Vec::new();
+        if let Err(e) = File::from(reader).read_to_end(&mut output) {
+            eprintln!("command substitution: {}", e);
+        }
+        let status = match sys::waitpid(pid, 0) {
+            Ok(Some(WaitStatus::Exited(status))) => status,
+            _ => 1,
+        };
+        // Ctrl-C while it runs abandons the command it is part of, like for a foreground job
+        if shell.job_control && status == 128 + libc::SIGINT {
+            println!();
+            shell.interrupted = true;
+        }
+        shell.substitution_status = Some(status);
+
+        let mut output = String::from_utf8_lossy(&output).into_owned();
+        output.truncate(output.trim_end_matches('\n').len());
+        output
+    }
+
+    // Starts the command line in a forked copy of the shell, its stdout
+    // (`<(...)`) or stdin (`>(...)`) connected to a pipe, and returns the
+    // /dev/fd path of the shell's end. That end stays open until the command
+    // the word belongs to is done; the process is reaped after it.
+    fn process_substitution(shell: &mut Shell, command: &str, output: bool) -> String {
+        let Some(list) = Self::parse(shell, command) else {
+            return String::new();
+        };
+        let (reader, writer) = match sys::pipe() {
+            Ok(pipe) => pipe,
+            Err(e) => {
+                eprintln!("pipe: {}", e);
+                return String::new();
+            }
+        };
+        let (ours, theirs, target) = match output {
+            true => (reader, writer, 1),
+            false => (writer, reader, 0),
+        };
+        match sys::fork() {
+            Ok(Fork::Child) => {
+                drop(ours);
+                // the other substitutions of the command are not its business
+                shell.substitution_fds.clear();
+                shell.substitution_pids.clear();
+                shell.job_control = false;
+                shell.enter_job(0, false);
+                shell.source = command.to_string();
+                if let Err(e) = sys::dup2(theirs.as_raw_fd(), target) {
+                    eprintln!("dup2: {}", e);
+                    sys::exit(1);
+                }
+                drop(theirs);
+                Executor::execute(shell, &list);
+                sys::exit(shell.last_status);
+            }
+            Ok(Fork::Parent(pid)) => shell.substitution_pids.push(pid),
+            Err(e) => {
+                eprintln!("fork: {}", e);
+                return String::new();
+            }
+        }
+
+        drop(theirs);
+        // the command runs with our end at the same number
+        if let Err(e) = sys::clear_cloexec(ours.as_raw_fd()) {
+            eprintln!("process substitution: {}", e);
+        }
+        let path = format!("/dev/fd/{}", ours.as_raw_fd());
+        shell.substitution_fds.push(ours);
+        path
+    }
+
+    // Parses the command line of a substitution. A syntax error is reported
+    // and becomes its status.
+    fn parse(shell: &mut Shell, command: &str) -> Option<CommandList> {
+        match Parser::parse(Tokenizer::tokenize(command, 1)) {
+            Ok(list) => Some(list),
+            Err(error) => {
+                eprintln!("{}", error);
+                shell.substitution_status = Some(2);
+                None
+            }
+        }
+    }
+
+    // The fields one word expands to.
+    fn split(parts: Vec<Part>) -> Vec<Field> {
+        let mut fields = Vec::new();
+        let mut field: Option<Field> = None;
+        for part in parts {
+            // even an empty quoted part makes a field
+            if !part.split {
+                field.get_or_insert_with(Field::default);
+            }
+            for c in part.text.chars() {
+                if part.split && IFS.contains(c) {
+                    fields.extend(field.take());
+                    continue;
+                }
+                let field = field.get_or_insert_with(Field::default);
+                field.text.push(c);
+                if part.quoted {
+                    field.pattern.push_str(&pattern::escape(c.encode_utf8(&mut [0; 4])));
+                } else {
+                    field.pattern.push(c);
+                    field.glob |= "*?[".contains(c);
+                }
+            }
+        }
+        fields.extend(field);
+        fields
+    }
+}
+
+// Splits the text after the name in a `${...}` into the operator and its
+// word. None when there is text but no operator.
+fn operator(text: &str) -> Option<(&str, &str)> {
+    const OPERATORS: [&str; 21] = [
+        ":-", ":=", ":?", ":+", "##", "%%", "//", "/#", "/%", "^^", ",,", "-", "=", "?", "+",
+        "#", "%", "/", ":", "^", ",",
+    ];
+    if text.is_empty() {
+        return Some(("", ""));
+    }
+    let operator = OPERATORS.into_iter().find(|operator| text.starts_with(operator))?;
+    Some((operator, &text[operator.len()..]))
+}
+
+// Splits the word of an operator at the first `separator` outside quotes and
+// substitutions, like the `/` between the pattern and the replacement.
+fn split_operand(text: &str, separator: char, quoted: bool) -> (&str, Option<&str>) {
+    let mut in_double_quotes = false;
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        let rest = &text[i..];
+        match c {
+            '\\' => i += rest[1..].chars().next().map_or(0, char::len_utf8),
+            '\'' if !quoted && !in_double_quotes => {
+                i += rest[1..].find('\'').map_or(rest.len() - 1, |len| len + 1);
+            }
+            '"' => in_double_quotes = !in_double_quotes,
+            '$' | '`' if c == '`' || rest.starts_with("$(") || rest.starts_with("${") => {
+                i += substitution_len(rest, quoted || in_double_quotes).unwrap_or(rest.len());
+                continue;
+            }
+            _ if c == separator && !in_double_quotes => {
+                return (&text[..i], Some(&rest[1..]));
+            }
+            _ => {}
+        }
+        i += c.len_utf8();
+    }
+    (text, None)
+}
+
+// Byte offsets of the character boundaries in the text, both ends included.
+fn boundaries(text: &str) -> impl DoubleEndedIterator<Item = usize> + '_ {
+    text.char_indices().map(|(i, _)| i).chain([text.len()])
+}
+
+// `${name/pattern/replacement}`: replaces the longest match of the pattern,
+// every one of them with `//`, only at the start with `/#` or at the end with `/%`.
+fn replace(value: &str, pattern: &Pattern, replacement: &str, operator: &str) -> String {
+    let mut result = String::new();
+    let mut start = 0;
+    while start <= value.len() {
+        let found = boundaries(value).filter(|&from| from >= start).find_map(|from| {
+            if operator == "/#" && from > 0 {
+                return None;
+            }
+            let mut ends = boundaries(value).rev().filter(|&end| end >= from);
+            let end = match operator {
+                "/%" => Some(value.len()).filter(|&end| pattern.matches(&value[from..end])),
+                _ => ends.find(|&end| pattern.matches(&value[from..end])),
+            }?;
+            Some((from, end))
+        });
+        let Some((from, end)) = found else {
+            break;
+        };
+        result.push_str(&value[start..from]);
+        result.push_str(replacement);
+        start = end;
+        if operator != "//" {
+            break;
+        }
+        if from == end {
+            // an empty match: keep the next character and go on after it
+            match value[end..].chars().next() {
+                Some(c) => {
+                    result.push(c);
+                    start += c.len_utf8();
+                }
+                None => return result,
+            }
+        }
+    }
+    result.push_str(&value[start.min(value.len())..]);
+    result
+}
+
+// `${name:offset:length}` counts characters; a negative offset counts from
+// the end, and so does a negative length, for where the substring stops.
+// None when it would stop before it starts.
+fn substring(value: &str, offset: i64, length: Option<i64>) -> Option<String> {
+    let count = value.chars().count() as i64;
+    let start = if offset < 0 { count + offset } else { offset };
+    if start < 0 || start > count {
+        return Some(String::new());
+    }
+    let end = match length {
+        None => count,
+        Some(length) if length < 0 => count + length,
+        Some(length) => (start + length).min(count),
+    };
+    if end < start {
+        return None;
+    }
+    Some(value.chars().skip(start as usize).take((end - start) as usize).collect())
+}
+
+// The parameter name `text` starts with: a special one like `?` or `@`, a
+// single digit, or the longest variable name; empty when there is none.
+fn parameter_name(text: &str) -> &str {
+    if text.starts_with(|c: char| "?#@*".contains(c) || c.is_ascii_digit()) {
+        return &text[..1];
+    }
+    let len = text
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(text.len());
+    match is_name(&text[..len]) {
+        true => &text[..len],
+        false => "",
+    }
+}
+
+// The directory a tilde prefix names, looking other users up in /etc/passwd.
+fn home(shell: &Shell, name: &str) -> Option<String> {
+    let variable = match name {
+        "" => "HOME",
+        "+" => "PWD",
+        "-" => "OLDPWD",
+        _ => {
+            let passwd = std::fs::read_to_string("/etc/passwd").ok()?;
+            // name:password:uid:gid:comment:home:shell
+            return passwd.lines().find_map(|line| {
+                let fields: Vec<&str> = line.split(':').collect();
+                match fields[..] {
+                    [user, _, _, _, _, home, ..] if user == name => Some(home.to_string()),
+                    _ => None,
+                }
+            });
+        }
+    };
+    shell.variables.get(variable).map(str::to_string)
+}
+
+// Inside backquotes a backslash only escapes `$`, `` ` `` and `\`.
+fn unescape_backquoted(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.next_if(|&next| c == '\\' && "$`\\".contains(next)) {
+            Some(next) => result.push(next),
+            None => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::TokenKind;
+
+    // The arguments the line expands to.
+    fn expand(line: &str) -> Vec<String> {
+        expand_with(line, &[])
+    }
+
+    // The same inside a function called with `positional`.
+    fn expand_with(line: &str, positional: &[&str]) -> Vec<String> {
+        let words: Vec<Word> = Tokenizer::tokenize(line, 1)
+            .into_iter()
+            .filter_map(|token| match token.kind {
+                TokenKind::Word(word) => Some(word),
+                _ => None,
+            })
+            .collect();
+        let mut shell = Shell::new();
+        shell.positional = positional.iter().map(|arg| arg.to_string()).collect();
+        Expander::expand_words(&mut shell, &words).unwrap()
+    }
+
+    #[test]
+    fn empty_quotes_are_arguments() {
+        assert_eq!(expand(r#"echo "" x"#), ["echo", "", "x"]);
+        assert_eq!(expand(r#"echo x"""#), ["echo", "x"]);
+        assert_eq!(expand(r#"echo "" """#), ["echo", "", ""]);
+        assert_eq!(expand("echo ''"), ["echo", ""]);
+        assert_eq!(expand(r#"echo "$expand_unset" $expand_unset"#), ["echo", ""]);
+    }
+
+    #[test]
+    fn positional_parameters() {
+        let args = ["a b", "", "c"];
+        assert_eq!(expand_with("echo $# $1 $3", &args), ["echo", "3", "a", "b", "c"]);
+        assert_eq!(expand_with(r#"echo "$@""#, &args), ["echo", "a b", "", "c"]);
+        assert_eq!(expand_with(r#"echo x"${@}"y"#, &args), ["echo", "xa b", "", "cy"]);
+        assert_eq!(expand_with(r#"echo "$*""#, &args), ["echo", "a b  c"]);
+        assert_eq!(expand_with("echo $@", &args), ["echo", "a", "b", "c"]);
+        assert_eq!(expand_with(r#"echo "$@""#, &[]), ["echo"]);
+        let many: Vec<String> = (1..=11).map(|n| n.to_string()).collect();
+        let many: Vec<&str> = many.iter().map(String::as_str).collect();
+        assert_eq!(expand_with("echo ${10} $10", &many), ["echo", "10", "10"]);
+    }
+}
@@ -0,0 +1,123 @@
+use std::fs;
+
+use crate::pattern::Pattern;
+
+// The `shopt` options that change how patterns match file names.
+#[derive(Debug, Default)]
+pub struct GlobOptions {
+    pub nullglob: bool,  // a pattern that matches nothing disappears
+    pub failglob: bool,  // a pattern that matches nothing fails the command
+    pub dotglob: bool,   // `*` and `?` also match a leading `.`
+    pub globstar: bool,  // `**` as a whole path component matches any depth of directories
+}
+
+impl GlobOptions {
+    pub const NAMES: [&'static str; 4] = ["dotglob", "failglob", "globstar", "nullglob"];
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "dotglob" => Some(&mut self.dotglob),
+            "failglob" => Some(&mut self.failglob),
+            "globstar" => Some(&mut self.globstar),
+            "nullglob" => Some(&mut self.nullglob),
+            _ => None,
+        }
+    }
+}
+
+// The paths the pattern matches, sorted. Each `/`-separated component is
+// matched against the entries of the directories the previous ones matched;
+// a name starting with `.` is only matched by a pattern that starts with one,
+// unless `dotglob` is set.
+pub fn expand(pattern: &str, options: &GlobOptions) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_string()], rest),
+        None => (vec![String::new()], pattern),
+    };
+    let components: Vec<&str> = rest.split('/').collect();
+    for (i, component) in components.iter().enumerate() {
+        let last = i == components.len() - 1;
+        let mut next = Vec::new();
+        for path in &paths {
+            if component.is_empty() {
+                // `a//b`, or a trailing `/` that only keeps directories
+                if is_dir(path) {
+                    next.push(format!("{}/", path.trim_end_matches('/')));
+                }
+                continue;
+            }
+            if *component == "**" && options.globstar {
+                // this directory and every one below it, or with nothing after
+                // the `**` every file below it too
+                if !last {
+                    next.push(path.clone());
+                }
+                walk(path, options, !last, &mut next);
+                continue;
+            }
+            let pattern = Pattern::new(component);
+            if let Some(name) = pattern.literal() {
+                // no need to read the directory, the name is there or not
+                let joined = join(path, &name);
+                if fs::symlink_metadata(&joined).is_ok() {
+                    next.push(joined);
+                }
+                continue;
+            }
+            let hidden = options.dotglob || component.starts_with('.') || component.starts_with("\\.");
+            for name in entries(path) {
+                if (hidden || !name.starts_with('.')) && pattern.matches(&name) {
+                    let joined = join(path, &name);
+                    if last || is_dir(&joined) {
+                        next.push(joined);
+                    }
+                }
+            }
+        }
+        paths = next;
+    }
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+// The names in the directory, "" being the current one. Empty when it can't be read.
+fn entries(path: &str) -> Vec<String> {
+    let dir = if path.is_empty() { "." } else { path };
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// Adds what is below the directory, depth first: only the directories when
+// `dirs_only`. Symbolic links to directories aren't followed.
+fn walk(path: &str, options: &GlobOptions, dirs_only: bool, found: &mut Vec<String>) {
+    for name in entries(path) {
+        if name.starts_with('.') && !options.dotglob {
+            continue;
+        }
+        let joined = join(path, &name);
+        let dir = fs::symlink_metadata(&joined).is_ok_and(|metadata| metadata.is_dir());
+        if dir || !dirs_only {
+            found.push(joined.clone());
+        }
+        if dir {
+            walk(&joined, options, dirs_only, found);
+        }
+    }
+}
+
+fn join(path: &str, name: &str) -> String {
+    match path {
+        "" => name.to_string(),
+        _ if path.ends_with('/') => format!("{}{}", path, name),
+        _ => format!("{}/{}", path, name),
+    }
+}
+
+fn is_dir(path: &str) -> bool {
+    fs::metadata(if path.is_empty() { "." } else { path }).is_ok_and(|metadata| metadata.is_dir())
+}
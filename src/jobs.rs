@@ -0,0 +1,255 @@
+use std::fmt;
+use std::io;
+
+use crate::sys::{self, WaitStatus};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobState {
+    Running,
+    Stopped,
+    Done(i32), // exit status of the last process in the pipeline
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobState::Running => write!(f, "Running"),
+            JobState::Stopped => write!(f, "Stopped"),
+            JobState::Done(0) => write!(f, "Done"),
+            JobState::Done(status) => write!(f, "Exit {}", status),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Process {
+    pub pid: libc::pid_t,
+    pub status: Option<i32>, // set once the process has been reaped
+    pub stopped: bool,
+}
+
+pub struct Job {
+    pub id: usize,          // 0 until the job is put in the table
+    pub pgid: libc::pid_t,  // process group shared by every stage
+    pub processes: Vec<Process>, // one per pipeline stage, the last one decides the status
+    pub command: String,    // the pipeline as typed, shown by the job messages
+    pub tmodes: Option<libc::termios>, // terminal settings the job had when it stopped
+    notified: JobState,     // last state reported to the user
+}
+
+impl Job {
+    pub fn new(pgid: libc::pid_t, pids: Vec<libc::pid_t>, command: String) -> Self {
+        Self {
+            id: 0,
+            pgid,
+            processes: pids
+                .into_iter()
+                .map(|pid| Process {
+                    pid,
+                    status: None,
+                    stopped: false,
+                })
+                .collect(),
+            command,
+            tmodes: None,
+            notified: JobState::Running,
+        }
+    }
+
+    pub fn state(&self) -> JobState {
+        let mut running = self.processes.iter().filter(|p| p.status.is_none());
+        match running.next() {
+            None => JobState::Done(self.processes.last().and_then(|p| p.status).unwrap_or(0)),
+            Some(_) if self.processes.iter().any(|p| p.stopped) => JobState::Stopped,
+            Some(_) => JobState::Running,
+        }
+    }
+
+    // Blocks until every process has exited or one of them has been stopped.
+    pub fn wait(&mut self) {
+        for process in self.processes.iter_mut().filter(|p| p.status.is_none()) {
+            loop {
+                match sys::waitpid(process.pid, libc::WUNTRACED) {
+                    Ok(Some(WaitStatus::Exited(status))) => process.status = Some(status),
+                    Ok(Some(WaitStatus::Stopped)) => {
+                        process.stopped = true;
+                        return;
+                    }
+                    Ok(_) => continue,
+                    // nothing left to wait for, the process is gone
+                    Err(_) => process.status = Some(1),
+                }
+                break;
+            }
+        }
+    }
+
+    // Collects state changes without blocking.
+    pub fn poll(&mut self) {
+        let options = libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED;
+        for process in self.processes.iter_mut().filter(|p| p.status.is_none()) {
+            // drain every status change the child queued since the last poll
+            loop {
+                match sys::waitpid(process.pid, options) {
+                    Ok(Some(WaitStatus::Exited(status))) => process.status = Some(status),
+                    Ok(Some(WaitStatus::Stopped)) => {
+                        process.stopped = true;
+                        continue;
+                    }
+                    Ok(Some(WaitStatus::Continued)) => {
+                        process.stopped = false;
+                        continue;
+                    }
+                    Ok(None) => {}
+                    Err(_) => process.status = Some(1),
+                }
+                break;
+            }
+        }
+    }
+
+    // Sends SIGCONT to the whole process group.
+    pub fn resume(&mut self) -> io::Result<()> {
+        for process in self.processes.iter_mut() {
+            process.stopped = false;
+        }
+        self.notified = JobState::Running;
+        sys::kill(-self.pgid, libc::SIGCONT)
+    }
+}
+
+// Jobs that are running in the background or stopped, numbered the way
+// bash numbers them.
+#[derive(Default)]
+pub struct JobTable {
+    jobs: Vec<Job>,    // sorted by id
+    recent: Vec<usize>, // ids, the current job (`%+`) last and the previous one (`%-`) before it
+}
+
+impl JobTable {
+    pub fn new() -> Self {
+        Self {
+            jobs: Vec::new(),
+            recent: Vec::new(),
+        }
+    }
+
+    // Adds the job and makes it the current one. A job coming back from the
+    // foreground keeps the number it had.
+    pub fn add(&mut self, mut job: Job) -> usize {
+        if job.id == 0 {
+            job.id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        }
+        job.notified = job.state();
+        let id = job.id;
+        let at = self.jobs.partition_point(|other| other.id < id);
+        self.jobs.insert(at, job);
+        self.recent.retain(|&other| other != id);
+        self.recent.push(id);
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        let at = self.jobs.iter().position(|job| job.id == id)?;
+        self.recent.retain(|&other| other != id);
+        Some(self.jobs.remove(at))
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    // Resolves a job spec (`%n`, `%+`, `%%`, `%-`, `%prefix` or no spec at all
+    // for the current job) to a job number.
+    pub fn resolve(&self, spec: Option<&str>) -> Result<usize, String> {
+        let spec = spec.unwrap_or("%+");
+        let name = spec.strip_prefix('%').unwrap_or(spec);
+        let id = match name {
+            "" | "+" | "%" => self.recent.last().copied(),
+            "-" => self.recent.iter().rev().nth(1).copied(),
+            _ => match name.parse::<usize>() {
+                Ok(id) => self.jobs.iter().find(|job| job.id == id).map(|job| job.id),
+                Err(_) => self
+                    .jobs
+                    .iter()
+                    .find(|job| job.command.starts_with(name))
+                    .map(|job| job.id),
+            },
+        };
+        id.ok_or_else(|| {
+            let spec = if matches!(name, "" | "+" | "%") { "current" } else { spec };
+            format!("{}: no such job", spec)
+        })
+    }
+
+    // Collects state changes of every job without blocking, then returns the
+    // lines announcing the ones the user hasn't heard about and forgets the
+    // jobs that are done.
+    pub fn reap(&mut self) -> Vec<String> {
+        for job in self.jobs.iter_mut() {
+            job.poll();
+        }
+        let messages = self
+            .jobs
+            .iter()
+            .filter(|job| job.state() != job.notified)
+            .map(|job| self.format(job))
+            .collect();
+        for job in self.jobs.iter_mut() {
+            job.notified = job.state();
+        }
+        self.forget_done();
+        messages
+    }
+
+    // Lines printed by the `jobs` builtin.
+    pub fn list(&mut self) -> Vec<String> {
+        for job in self.jobs.iter_mut() {
+            job.poll();
+            job.notified = job.state();
+        }
+        let lines = self.jobs.iter().map(|job| self.format(job)).collect();
+        self.forget_done();
+        lines
+    }
+
+    // The job's line as printed by `jobs`.
+    pub fn describe(&self, id: usize) -> Option<String> {
+        self.jobs
+            .iter()
+            .find(|job| job.id == id)
+            .map(|job| self.format(job))
+    }
+
+    fn format(&self, job: &Job) -> String {
+        let marker = if self.recent.last() == Some(&job.id) {
+            '+'
+        } else if self.recent.iter().rev().nth(1) == Some(&job.id) {
+            '-'
+        } else {
+            ' '
+        };
+        let state = job.state();
+        let suffix = if state == JobState::Running { " &" } else { "" };
+        format!(
+            "[{}]{}  {:<24}{}{}",
+            job.id,
+            marker,
+            state.to_string(),
+            job.command,
+            suffix
+        )
+    }
+
+    fn forget_done(&mut self) {
+        let done: Vec<usize> = self
+            .jobs
+            .iter()
+            .filter(|job| matches!(job.state(), JobState::Done(_)))
+            .map(|job| job.id)
+            .collect();
+        for id in done {
+            self.remove(id);
+        }
+    }
+}
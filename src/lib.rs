@@ -1,5 +1,14 @@
+pub mod arithmetic;
+pub mod brace;
 pub mod command;
 pub mod executor;
+pub mod expand;
+pub mod glob;
+pub mod jobs;
 pub mod parser;
+pub mod pattern;
 pub mod tokenizer;
-pub mod auto_complete;
\ No newline at end of file
+pub mod auto_complete;
+pub mod shell;
+pub mod sys;
+pub mod variables;
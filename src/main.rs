@@ -3,15 +3,25 @@ use std::io::{self, Write};
 use rustyline::Editor;
 use rustyline::error::ReadlineError;
 
+pub mod arithmetic;
+pub mod brace;
 pub mod command;
 pub mod executor;
+pub mod expand;
+pub mod glob;
+pub mod jobs;
 pub mod parser;
+pub mod pattern;
 pub mod tokenizer;
 pub mod auto_complete;
+pub mod shell;
+pub mod sys;
+pub mod variables;
 
 use auto_complete::AutoCompleter;
 use executor::Executor;
-use parser::Parser;
+use parser::{ParseError, Parser};
+use shell::Shell;
 use tokenizer::Tokenizer;
 use rustyline::history::DefaultHistory;
 
@@ -20,31 +30,75 @@ fn main() {
     // Create rustyline editor with our completer
     let mut rl= Editor::<AutoCompleter ,DefaultHistory>::new().unwrap();
     rl.set_helper(Some(AutoCompleter::new()));
+    let mut shell = Shell::new();
+    shell.enable_job_control();
+    // scripts get line numbers in their error messages
+    let interactive = sys::isatty(libc::STDIN_FILENO);
+    let mut line_number = 0;
 
     loop {
+        // Reap process substitutions that outlived their command
+        shell.finish_substitutions(0);
+        // Report background jobs that finished since the last prompt
+        for message in shell.jobs.reap() {
+            println!("{}", message);
+        }
+
         // Read input line with prompt
         let readline = rl.readline("$ ");
         match readline {
             Ok(input_line) => {
-                let input_line = input_line.trim();
-                if input_line.is_empty() {
+                line_number += 1;
+                let first_line = line_number;
+                let mut input = input_line.trim().to_string();
+                if input.is_empty() {
                     continue;
                 }
 
-                // Add to history so you can navigate with up/down arrows
-                rl.add_history_entry(input_line);
-                println!("input = {}" , input_line);
+                // Tokenize and parse, reading more lines while the command is incomplete
+                let parsed = loop {
+                    match Parser::parse(Tokenizer::tokenize(&input, first_line)) {
+                        Err(ParseError::UnexpectedEof) => match rl.readline("> ") {
+                            Ok(more) => {
+                                line_number += 1;
+                                input.push('\n');
+                                input.push_str(&more);
+                            }
+                            Err(ReadlineError::Interrupted) => break None,
+                            Err(_) => break Some(Err(ParseError::UnexpectedEof)),
+                        },
+                        result => break Some(result),
+                    }
+                };
 
-                // Tokenize, parse, and execute using your existing shell logic
-                let tokens = Tokenizer::tokenize(input_line);
-                let parsed_line = Parser::parse(tokens);
+                // Add to history so you can navigate with up/down arrows
+                let _ = rl.add_history_entry(input.as_str());
 
-                // Execute your command
-                Executor::execute(parsed_line);
+                match parsed {
+                    Some(Ok(command_list)) => {
+                        shell.interrupted = false;
+                        shell.source = input;
+                        Executor::execute(&mut shell, &command_list);
+                    }
+                    Some(Err(error)) => {
+                        // nothing on the line runs
+                        match &error {
+                            ParseError::UnexpectedToken { span, .. } if interactive => {
+                                eprintln!("{}\n{}", error, span.underline(&input))
+                            }
+                            ParseError::UnexpectedToken { span, .. } => {
+                                eprintln!("line {}: {}\n{}", span.line, error, span.underline(&input))
+                            }
+                            ParseError::UnexpectedEof => eprintln!("{}", error),
+                        }
+                        shell.last_status = 2;
+                    }
+                    None => {}
+                }
             }
             Err(ReadlineError::Interrupted) => {
-                println!("CTRL-C");
-                break;
+                // Ctrl-C only throws away the line being edited
+                continue;
             }
             Err(ReadlineError::Eof) => {
                 println!("CTRL-D");
@@ -56,17 +110,13 @@ fn main() {
             }
         }
     }
+    // like other shells, leave with the status of the last command
+    std::process::exit(shell.last_status);
 }
 
-// // Execution environment (shell state)
-// pub struct Shell {
-//     pub current_dir: std::path::PathBuf,
-//     pub last_status: i32,
-// }
-
-// Tokenizer → produces Vec<Token>
+// Tokenizer → produces Vec<Token>, each with its Span in the input
 
-// Parser → converts tokens to ParsedLine
+// Parser → converts tokens to a CommandList (the syntax tree)
 
-// Executor → takes ParsedLine
-// Shell → stores state (pwd, last exit code, env if needed)
+// Executor → walks the CommandList
+// Shell → stores state (pwd, last exit code, variables)
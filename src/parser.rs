@@ -1,95 +1,786 @@
-use crate::command::{Command, RedirectCode, Redirection};
-use crate::tokenizer::Token;
+use crate::command::Redirection;
+use std::os::fd::RawFd;
+use crate::tokenizer::{Quoting, Segment, Span, Token, TokenKind, Word};
+use std::fmt;
+use std::rc::Rc;
+use thiserror::Error;
 
-#[derive(Debug)]
+// The syntax tree of a command line:
+//
+//   CommandList  = ListItem, ListItem, ...      separated by `;`, `&` or newlines
+//   ListItem     = AndOr run in the foreground or the background
+//   AndOr        = Pipeline && Pipeline || ...
+//   Pipeline     = [!] CommandNode | CommandNode | ...
+//   CommandNode  = SimpleCommand, or a CompoundCommand with its redirections
 
-pub struct Pipeline {
-    pub commands: Vec<Command>,
+#[derive(Debug, Default)]
+pub struct CommandList {
+    pub items: Vec<ListItem>,
 }
-#[derive(Debug)]
+
+#[derive(Debug, Clone, Copy)]
 pub enum ExecMode {
     Foreground,
     Background, // when you type & that mean you want this program run in Background
 }
 
+// One entry of a command list, terminated by `;`, `&`, a newline or the end of the list.
 #[derive(Debug)]
-pub struct ParsedLine {
-    pub pipeline: Pipeline,
+pub struct ListItem {
+    pub and_or: AndOr,
     pub mode: ExecMode,
 }
 
-pub struct Parser;
+#[derive(Debug)]
+pub enum Connector {
+    And, // && : run the next pipeline only if the previous one succeeded
+    Or,  // || : run the next pipeline only if the previous one failed
+}
 
-impl Parser {
-    pub fn parse(tokens: Vec<Token>) -> ParsedLine {
-        let mut commands = Vec::new();
-        let mut program = None;
-        let mut args = Vec::new();
-        let mut redirection = None;
-        let mut exec_mode = ExecMode::Foreground;
-
-        let mut iter = tokens.into_iter().peekable();
-        while let Some(token) = iter.next() {
-            match token {
-                Token::Word(word) => {
-                    if program.is_none() {
-                        program = Some(word)
-                    } else {
-                        args.push(word);
-                    }
+// Pipelines chained with `&&` and `||`, evaluated left to right.
+#[derive(Debug)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+}
+
+#[derive(Debug)]
+pub struct Pipeline {
+    pub negated: bool, // `! pipeline` inverts the exit status
+    pub commands: Vec<CommandNode>,
+}
+
+#[derive(Debug)]
+pub enum CommandNode {
+    Simple(SimpleCommand),
+    Compound(CompoundCommand, Vec<Redirection<Word>>),
+}
+
+// `NAME=value ... word ... > file ...`, before any expansion.
+#[derive(Debug, Default)]
+pub struct SimpleCommand {
+    pub assignments: Vec<(String, Word)>,
+    pub words: Vec<Word>, // the command name followed by its arguments
+    pub span: Span,         // where the command name is, for error messages
+    pub redirections: Vec<Redirection<Word>>,
+}
+
+#[derive(Debug)]
+pub enum CompoundCommand {
+    BraceGroup(CommandList), // { list; }
+    Subshell(CommandList),   // ( list ), runs in a forked copy of the shell
+    If {
+        branches: Vec<(CommandList, CommandList)>, // if/elif condition and its `then` body
+        else_body: Option<CommandList>,
+    },
+    While {
+        condition: CommandList,
+        body: CommandList,
+        until: bool, // `until` loops while the condition fails
+    },
+    For {
+        variable: String,
+        words: Vec<Word>,
+        body: CommandList,
+    },
+    Arithmetic(String), // ((expression)), true when it isn't 0
+    Case {
+        word: Word,
+        arms: Vec<CaseArm>, // tried in order, only the first match runs
+    },
+    // `name() compound-command`: running it defines the function, the body
+    // (a compound command with its redirections) runs when it is called
+    Function {
+        name: String,
+        body: Rc<CommandNode>,
+    },
+}
+
+// `pattern | pattern) list ;;` in a `case`.
+#[derive(Debug)]
+pub struct CaseArm {
+    pub patterns: Vec<Word>,
+    pub body: CommandList, // may be empty
+}
+
+impl fmt::Display for CommandList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, item) in self.items.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", item.and_or)?;
+            match item.mode {
+                ExecMode::Background => write!(f, " &")?,
+                ExecMode::Foreground if i + 1 < self.items.len() => write!(f, ";")?,
+                ExecMode::Foreground => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for AndOr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.first)?;
+        for (connector, pipeline) in &self.rest {
+            match connector {
+                Connector::And => write!(f, " && {}", pipeline)?,
+                Connector::Or => write!(f, " || {}", pipeline)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.negated {
+            write!(f, "! ")?;
+        }
+        for (i, command) in self.commands.iter().enumerate() {
+            if i > 0 {
+                write!(f, " | ")?;
+            }
+            write!(f, "{}", command)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CommandNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let redirections = match self {
+            CommandNode::Simple(simple) => {
+                let assignments = simple.assignments.iter().map(|(n, v)| format!("{}={}", n, v));
+                let words = assignments.chain(simple.words.iter().map(Word::to_string));
+                write!(f, "{}", words.collect::<Vec<_>>().join(" "))?;
+                &simple.redirections
+            }
+            CommandNode::Compound(compound, redirections) => {
+                write!(f, "{}", compound)?;
+                redirections
+            }
+        };
+        for redirection in redirections {
+            write!(f, " {}", redirection)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CompoundCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompoundCommand::BraceGroup(list) => write!(f, "{{ {}; }}", list),
+            CompoundCommand::Subshell(list) => write!(f, "({})", list),
+            CompoundCommand::If {
+                branches,
+                else_body,
+            } => {
+                for (i, (condition, body)) in branches.iter().enumerate() {
+                    let keyword = if i == 0 { "if" } else { " elif" };
+                    write!(f, "{} {}; then {};", keyword, condition, body)?;
                 }
-                Token::RedirectIn => {
-                    if let Some(Token::Word(file)) = iter.next() {
-                        redirection = Some(Redirection::Input(file));
-                    }
+                if let Some(body) = else_body {
+                    write!(f, " else {};", body)?;
                 }
-                Token::RedirectOut => {
-                    if let Some(Token::Word(file)) = iter.next() {
-                        redirection = Some(Redirection::OutputTruncate(RedirectCode::One(file)));
-                    }
+                write!(f, " fi")
+            }
+            CompoundCommand::While {
+                condition,
+                body,
+                until,
+            } => {
+                let keyword = if *until { "until" } else { "while" };
+                write!(f, "{} {}; do {}; done", keyword, condition, body)
+            }
+            CompoundCommand::For {
+                variable,
+                words,
+                body,
+            } => {
+                let words: Vec<String> = words.iter().map(Word::to_string).collect();
+                write!(f, "for {} in {}; do {}; done", variable, words.join(" "), body)
+            }
+            CompoundCommand::Arithmetic(expression) => write!(f, "(({}))", expression),
+            CompoundCommand::Case { word, arms } => {
+                write!(f, "case {} in", word)?;
+                for arm in arms {
+                    let patterns: Vec<String> = arm.patterns.iter().map(Word::to_string).collect();
+                    write!(f, " {}) {};;", patterns.join(" | "), arm.body)?;
                 }
-                Token::RedirectErrOut => {
-                    if let Some(Token::Word(file)) = iter.next() {
-                        redirection = Some(Redirection::OutputTruncate(RedirectCode::Two(file)));
-                    }
+                write!(f, " esac")
+            }
+            CompoundCommand::Function { name, body } => write!(f, "{}() {}", name, body),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+    #[error("syntax error near unexpected token '{token}'")]
+    UnexpectedToken { token: String, span: Span },
+    // the input stops in the middle of a command, more lines may complete it
+    #[error("syntax error: unexpected end of file")]
+    UnexpectedEof,
+}
+
+// Words that end the list in front of them when they start a command.
+const TERMINATORS: [&str; 8] = ["then", "elif", "else", "fi", "do", "done", "}", "esac"];
+
+pub struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    pub fn parse(tokens: Vec<Token>) -> Result<CommandList, ParseError> {
+        let mut parser = Parser { tokens, pos: 0 };
+        let list = parser.parse_list()?;
+        // the list only stops early at a `)` or closing keyword that closes nothing
+        if parser.peek().is_some() {
+            return Err(parser.unexpected());
+        }
+        Ok(list)
+    }
+
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.pos).map(|token| &token.kind)
+    }
+
+    // Consumes the current token if it is a word and hands it out.
+    fn next_word(&mut self) -> Option<Word> {
+        let TokenKind::Word(word) = &mut self.tokens.get_mut(self.pos)?.kind else {
+            return None;
+        };
+        self.pos += 1;
+        // the slot is never read again
+        Some(std::mem::take(word))
+    }
+
+    // The current token when it is the unquoted word `word`.
+    fn peek_word(&self, word: &str) -> bool {
+        self.peek_bare() == Some(word)
+    }
+
+    // The current token when it is an unquoted word; quoting a reserved
+    // word (`"if"`) makes it an ordinary one.
+    fn peek_bare(&self) -> Option<&str> {
+        match self.peek() {
+            Some(TokenKind::Word(word)) => word.as_bare(),
+            _ => None,
+        }
+    }
+
+    // Consumes the keyword, which must come next.
+    fn expect(&mut self, word: &str) -> Result<(), ParseError> {
+        if !self.peek_word(word) {
+            return Err(self.unexpected());
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    // The error for the current token, or for running out of them.
+    fn unexpected(&self) -> ParseError {
+        match self.tokens.get(self.pos) {
+            Some(Token {
+                kind: TokenKind::Unfinished,
+                ..
+            })
+            | None => ParseError::UnexpectedEof,
+            Some(token) => ParseError::UnexpectedToken {
+                token: token.kind.to_string(),
+                span: token.span,
+            },
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while let Some(TokenKind::Newline) = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn at_list_end(&self) -> bool {
+        match self.peek() {
+            None | Some(TokenKind::RParen | TokenKind::DoubleSemicolon) => true,
+            Some(TokenKind::Word(word)) => word.as_bare().is_some_and(|w| TERMINATORS.contains(&w)),
+            _ => false,
+        }
+    }
+
+    fn parse_list(&mut self) -> Result<CommandList, ParseError> {
+        let mut list = CommandList::default();
+        loop {
+            self.skip_newlines();
+            if self.at_list_end() {
+                return Ok(list);
+            }
+            let and_or = self.parse_and_or()?;
+            let mode = match self.peek() {
+                Some(TokenKind::Ampersand) => ExecMode::Background,
+                _ => ExecMode::Foreground,
+            };
+            match self.peek() {
+                Some(TokenKind::Semicolon | TokenKind::Ampersand | TokenKind::Newline) => self.pos += 1,
+                _ if self.at_list_end() => {}
+                _ => return Err(self.unexpected()),
+            }
+            list.items.push(ListItem { and_or, mode });
+        }
+    }
+
+    // The list inside a compound command, which can't be empty.
+    fn parse_body(&mut self) -> Result<CommandList, ParseError> {
+        let list = self.parse_list()?;
+        if list.items.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(list)
+    }
+
+    fn parse_and_or(&mut self) -> Result<AndOr, ParseError> {
+        let first = self.parse_pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            let connector = match self.peek() {
+                Some(TokenKind::AndIf) => Connector::And,
+                Some(TokenKind::OrIf) => Connector::Or,
+                _ => break,
+            };
+            self.pos += 1;
+            self.skip_newlines();
+            rest.push((connector, self.parse_pipeline()?));
+        }
+        Ok(AndOr { first, rest })
+    }
+
+    fn parse_pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let negated = self.peek_word("!");
+        if negated {
+            self.pos += 1;
+        }
+        let mut commands = vec![self.parse_command()?];
+        while let Some(TokenKind::Pipe | TokenKind::PipeBoth) = self.peek() {
+            if let (Some(TokenKind::PipeBoth), Some(command)) = (self.peek(), commands.last_mut()) {
+                // `a |& b` is `a 2>&1 | b`, after a's own redirections
+                let redirections = match command {
+                    CommandNode::Simple(simple) => &mut simple.redirections,
+                    CommandNode::Compound(_, redirections) => redirections,
+                };
+                redirections.push(Redirection::Duplicate(2, Word::bare("1")));
+            }
+            self.pos += 1;
+            self.skip_newlines();
+            commands.push(self.parse_command()?);
+        }
+        Ok(Pipeline { negated, commands })
+    }
+
+    fn parse_command(&mut self) -> Result<CommandNode, ParseError> {
+        if self.at_list_end() {
+            return Err(self.unexpected());
+        }
+        let compound = match self.peek() {
+            Some(TokenKind::LParen) => {
+                self.pos += 1;
+                let list = self.parse_body()?;
+                if !matches!(self.peek(), Some(TokenKind::RParen)) {
+                    return Err(self.unexpected());
                 }
-                Token::RedirectAppend => {
-                    if let Some(Token::Word(file)) = iter.next() {
-                        redirection = Some(Redirection::OutputAppend(RedirectCode::One(file)));
-                    }
+                self.pos += 1;
+                CompoundCommand::Subshell(list)
+            }
+            Some(TokenKind::Arithmetic(expression)) => {
+                let expression = expression.clone();
+                self.pos += 1;
+                CompoundCommand::Arithmetic(expression)
+            }
+            Some(TokenKind::Word(word)) => match word.as_bare().unwrap_or_default() {
+                "{" => {
+                    self.pos += 1;
+                    let list = self.parse_body()?;
+                    self.expect("}")?;
+                    CompoundCommand::BraceGroup(list)
                 }
-                Token::RedirectErrAppend => {
-                    if let Some(Token::Word(file)) = iter.next() {
-                        redirection = Some(Redirection::OutputAppend(RedirectCode::Two(file)));
-                    }
+                "if" => self.parse_if()?,
+                "while" | "until" => self.parse_while()?,
+                "for" => self.parse_for()?,
+                "case" => self.parse_case()?,
+                name if is_name(name) && self.function_follows() => self.parse_function()?,
+                _ => return self.parse_simple_command().map(CommandNode::Simple),
+            },
+            _ => return self.parse_simple_command().map(CommandNode::Simple),
+        };
+        let mut redirections = Vec::new();
+        while self.parse_redirection(&mut redirections)? {}
+        Ok(CommandNode::Compound(compound, redirections))
+    }
+
+    fn parse_if(&mut self) -> Result<CompoundCommand, ParseError> {
+        let mut branches = Vec::new();
+        let mut else_body = None;
+        self.expect("if")?;
+        loop {
+            let condition = self.parse_body()?;
+            self.expect("then")?;
+            let body = self.parse_body()?;
+            branches.push((condition, body));
+            if self.peek_word("elif") {
+                self.pos += 1;
+                continue;
+            }
+            if self.peek_word("else") {
+                self.pos += 1;
+                else_body = Some(self.parse_body()?);
+            }
+            self.expect("fi")?;
+            break;
+        }
+        Ok(CompoundCommand::If {
+            branches,
+            else_body,
+        })
+    }
+
+    fn parse_while(&mut self) -> Result<CompoundCommand, ParseError> {
+        let until = self.peek_word("until");
+        self.pos += 1;
+        let condition = self.parse_body()?;
+        self.expect("do")?;
+        let body = self.parse_body()?;
+        self.expect("done")?;
+        Ok(CompoundCommand::While {
+            condition,
+            body,
+            until,
+        })
+    }
+
+    fn parse_for(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.pos += 1;
+        let variable = match self.peek_bare() {
+            Some(name) if is_name(name) => name.to_string(),
+            _ => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        self.skip_newlines();
+        let mut words = Vec::new();
+        if self.peek_word("in") {
+            self.pos += 1;
+            while let Some(word) = self.next_word() {
+                words.push(word);
+            }
+        }
+        if let Some(TokenKind::Semicolon) = self.peek() {
+            self.pos += 1;
+        }
+        self.skip_newlines();
+        self.expect("do")?;
+        let body = self.parse_body()?;
+        self.expect("done")?;
+        Ok(CompoundCommand::For {
+            variable,
+            words,
+            body,
+        })
+    }
+
+    // Whether the name that comes next is followed by `()`.
+    fn function_follows(&self) -> bool {
+        let kinds = self.tokens[self.pos + 1..].iter().map(|token| &token.kind);
+        matches!(kinds.take(2).collect::<Vec<_>>()[..], [TokenKind::LParen, TokenKind::RParen])
+    }
+
+    // `name() compound-command [redirections]`, the body possibly on the next line.
+    fn parse_function(&mut self) -> Result<CompoundCommand, ParseError> {
+        let name = self.peek_bare().unwrap_or_default().to_string();
+        self.pos += 3;
+        self.skip_newlines();
+        // only a compound command can be the body
+        let compound = matches!(self.peek(), Some(TokenKind::LParen | TokenKind::Arithmetic(_)))
+            || matches!(self.peek_bare(), Some("{" | "if" | "while" | "until" | "for" | "case"));
+        if !compound {
+            return Err(self.unexpected());
+        }
+        Ok(CompoundCommand::Function {
+            name,
+            body: Rc::new(self.parse_command()?),
+        })
+    }
+
+    // `case word in pattern | pattern) list ;; ... esac`, each pattern list
+    // optionally starting with `(`. The `;;` of the last arm can be left out.
+    fn parse_case(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.pos += 1;
+        let Some(word) = self.next_word() else {
+            return Err(self.unexpected());
+        };
+        self.skip_newlines();
+        self.expect("in")?;
+        let mut arms = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.peek_word("esac") {
+                self.pos += 1;
+                break;
+            }
+            if let Some(TokenKind::LParen) = self.peek() {
+                self.pos += 1;
+            }
+            let mut patterns = Vec::new();
+            loop {
+                let Some(pattern) = self.next_word() else {
+                    return Err(self.unexpected());
+                };
+                patterns.push(pattern);
+                match self.peek() {
+                    Some(TokenKind::Pipe) => self.pos += 1,
+                    Some(TokenKind::RParen) => break,
+                    _ => return Err(self.unexpected()),
                 }
-                Token::Pipe => {
-                    if let Some(_program) = program.take() {
-                        // take : take the value and  make the program = None
-                        commands.push(Command {
-                            program: _program,
-                            args: args,
-                            redirection: redirection.take(),
-                        });
-                        args = Vec::new();
-                    }
+            }
+            self.pos += 1;
+            let body = self.parse_list()?;
+            match self.peek() {
+                Some(TokenKind::DoubleSemicolon) => self.pos += 1,
+                _ if self.peek_word("esac") => {}
+                _ => return Err(self.unexpected()),
+            }
+            arms.push(CaseArm { patterns, body });
+        }
+        Ok(CompoundCommand::Case { word, arms })
+    }
+
+    fn parse_simple_command(&mut self) -> Result<SimpleCommand, ParseError> {
+        let mut command = SimpleCommand::default();
+        loop {
+            if self.parse_redirection(&mut command.redirections)? {
+                continue;
+            }
+            let span = self.tokens.get(self.pos).map(|token| token.span);
+            let Some(word) = self.next_word() else {
+                break;
+            };
+            // NAME=value in front of the command name is an assignment
+            match assignment(&word) {
+                Some(assignment) if command.words.is_empty() => {
+                    command.assignments.push(assignment)
                 }
-                Token::Ampersand => {
-                    exec_mode = ExecMode::Background;
+                _ => {
+                    if command.words.is_empty() {
+                        command.span = span.unwrap_or_default();
+                    }
+                    command.words.push(word)
                 }
             }
         }
-        // Push last command if exist
-        if let Some(_program) = program.take() {
-            commands.push(Command {
-                program: _program,
-                args,
-                redirection: redirection.take(),
-            });
+        let empty = command.words.is_empty()
+            && command.assignments.is_empty()
+            && command.redirections.is_empty();
+        if empty {
+            return Err(self.unexpected());
+        }
+        Ok(command)
+    }
+
+    // Parses the redirection that comes next, if any, into `redirections`.
+    // `&> file` is two of them: `> file 2>&1`.
+    fn parse_redirection(
+        &mut self,
+        redirections: &mut Vec<Redirection<Word>>,
+    ) -> Result<bool, ParseError> {
+        let io_number = match self.peek() {
+            Some(&TokenKind::IoNumber(fd)) => {
+                self.pos += 1;
+                Some(fd)
+            }
+            _ => None,
+        };
+        if let Some(TokenKind::HereDoc { body, .. }) = self.tokens.get_mut(self.pos).map(|t| &mut t.kind) {
+            let body = body.take();
+            self.pos += 1;
+            // the delimiter, the tokenizer already used it to find the body
+            if self.next_word().is_none() {
+                return Err(self.missing_target());
+            }
+            let Some(body) = body else {
+                return Err(ParseError::UnexpectedEof);
+            };
+            redirections.push(Redirection::HereDoc(io_number.unwrap_or(0), body));
+            return Ok(true);
+        }
+        let build: fn(RawFd, Word) -> Redirection<Word> = match self.peek() {
+            Some(TokenKind::RedirectIn) => Redirection::Input,
+            Some(TokenKind::HereString) => |fd, mut word| {
+                // the word is read as a line
+                word.segments.push(Segment {
+                    text: "\n".to_string(),
+                    quoting: Quoting::Single,
+                });
+                Redirection::HereDoc(fd, word)
+            },
+            Some(TokenKind::RedirectOut | TokenKind::RedirectBoth) => Redirection::OutputTruncate,
+            Some(TokenKind::RedirectAppend | TokenKind::AppendBoth) => Redirection::OutputAppend,
+            Some(TokenKind::DupOut | TokenKind::DupIn) => Redirection::Duplicate,
+            // the tokenizer only makes a number an IoNumber in front of an operator
+            _ => return Ok(false),
+        };
+        let default_fd = match self.peek() {
+            Some(TokenKind::RedirectIn | TokenKind::DupIn | TokenKind::HereString) => 0,
+            _ => 1,
+        };
+        let both = matches!(self.peek(), Some(TokenKind::RedirectBoth | TokenKind::AppendBoth));
+        let dup_out = matches!(self.peek(), Some(TokenKind::DupOut));
+        self.pos += 1;
+
+        let Some(target) = self.next_word() else {
+            return Err(self.missing_target());
+        };
+        // `>& file` names a file rather than a descriptor: it means `&> file`
+        let text = target.text();
+        let to_file = dup_out
+            && io_number.is_none()
+            && text != "-"
+            && !text.bytes().all(|b| b.is_ascii_digit());
+        if both || to_file {
+            let build = if to_file { Redirection::OutputTruncate } else { build };
+            redirections.push(build(1, target));
+            redirections.push(Redirection::Duplicate(2, Word::bare("1")));
+        } else {
+            redirections.push(build(io_number.unwrap_or(default_fd), target));
         }
-        ParsedLine {
-            pipeline: Pipeline { commands },
-            mode: exec_mode,
+        Ok(true)
+    }
+
+    // The error for a redirection operator that isn't followed by a word.
+    fn missing_target(&self) -> ParseError {
+        match self.tokens.get(self.pos - 1) {
+            // the operator ends the line: like bash, blame the newline after it
+            Some(operator) if self.peek().is_none() => ParseError::UnexpectedToken {
+                token: "newline".to_string(),
+                span: Span {
+                    start: operator.span.end,
+                    ..operator.span
+                },
+            },
+            _ => self.unexpected(),
+        }
+    }
+}
+
+// A valid variable name: a letter or `_`, then letters, digits and `_`.
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Splits `NAME=value` into the name and the value. The name and the `=`
+// must be unquoted, the value keeps its quoting.
+fn assignment(word: &Word) -> Option<(String, Word)> {
+    let (first, rest) = word.segments.split_first()?;
+    if first.quoting != Quoting::Bare {
+        return None;
+    }
+    let (name, value) = first.text.split_once('=')?;
+    if !is_name(name) {
+        return None;
+    }
+    let mut segments = Vec::new();
+    if !value.is_empty() {
+        segments.push(Segment {
+            text: value.to_string(),
+            quoting: Quoting::Bare,
+        });
+    }
+    segments.extend(rest.iter().cloned());
+    Some((name.to_string(), Word { segments }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::Tokenizer;
+
+    fn parse(input: &str) -> Result<CommandList, ParseError> {
+        Parser::parse(Tokenizer::tokenize(input, 1))
+    }
+
+    // The token a syntax error blames, and where it is in the input.
+    fn unexpected(input: &str) -> (String, usize, usize) {
+        match parse(input) {
+            Err(ParseError::UnexpectedToken { token, span }) => (token, span.start, span.end),
+            result => panic!("{:?} parsed as {:?}", input, result),
+        }
+    }
+
+    #[test]
+    fn unexpected_tokens() {
+        assert_eq!(unexpected("echo >"), ("newline".to_string(), 6, 6));
+        assert_eq!(unexpected("| ls"), ("|".to_string(), 0, 1));
+        assert_eq!(unexpected("&"), ("&".to_string(), 0, 1));
+        assert_eq!(unexpected("a && && b"), ("&&".to_string(), 5, 7));
+        assert_eq!(unexpected(")"), (")".to_string(), 0, 1));
+        assert_eq!(unexpected("echo > | x"), ("|".to_string(), 7, 8));
+    }
+
+    #[test]
+    fn complete_commands() {
+        assert!(parse("echo a &").is_ok());
+        assert!(parse("echo a; echo b &").is_ok());
+        assert!(parse("").is_ok());
+    }
+
+    #[test]
+    fn incomplete_input() {
+        for input in ["if true", "cat <<EOF", "ls |", "a &&", "echo \"abc", "( echo", "while true; do"] {
+            assert!(matches!(parse(input), Err(ParseError::UnexpectedEof)), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn case_arms() {
+        let list = parse("case $f in *.rs) echo rust;; (*.md | *.txt) echo text;; *) esac").unwrap();
+        let command = &list.items[0].and_or.first.commands[0];
+        let CommandNode::Compound(CompoundCommand::Case { word, arms }, _) = command else {
+            panic!("not a case: {}", list);
+        };
+        assert_eq!(word.text(), "$f");
+        let patterns: Vec<Vec<String>> = arms
+            .iter()
+            .map(|arm| arm.patterns.iter().map(Word::text).collect())
+            .collect();
+        assert_eq!(patterns, [vec!["*.rs"], vec!["*.md", "*.txt"], vec!["*"]]);
+        assert!(arms[2].body.items.is_empty());
+        assert!(parse("case x in\n  x)\n    echo a\n    ;;\nesac").is_ok());
+        assert!(matches!(parse("case x in x) echo"), Err(ParseError::UnexpectedEof)));
+        assert_eq!(unexpected("case x in x echo;; esac"), ("echo".to_string(), 12, 16));
+        assert_eq!(unexpected("echo a;;"), (";;".to_string(), 6, 8));
+    }
+
+    #[test]
+    fn function_definitions() {
+        let list = parse("greet() { echo hi; } > out").unwrap();
+        let command = &list.items[0].and_or.first.commands[0];
+        let CommandNode::Compound(CompoundCommand::Function { name, body }, _) = command else {
+            panic!("not a function: {}", list);
+        };
+        assert_eq!(name, "greet");
+        let CommandNode::Compound(CompoundCommand::BraceGroup(_), redirections) = &**body else {
+            panic!("not a group: {}", body);
+        };
+        assert_eq!(redirections.len(), 1);
+        for input in ["f()\n{ a; }", "f() ( a )", "f() (( 1 ))", "f() if a; then b; fi"] {
+            assert!(parse(input).is_ok(), "{:?}", input);
         }
+        assert_eq!(unexpected("f() echo hi"), ("echo".to_string(), 4, 8));
+        assert!(matches!(parse("f()"), Err(ParseError::UnexpectedEof)));
+        // not a name, so `(` is out of place
+        assert_eq!(unexpected("1f() { a; }"), ("(".to_string(), 2, 3));
     }
 }
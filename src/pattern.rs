@@ -0,0 +1,266 @@
+// Shell patterns, as used by `${name#pattern}` and friends: `*` matches any
+// string, `?` any character, `[...]` one of a set, and a backslash makes the
+// next character stand for itself.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    Literal(char),
+    Any,  // ?
+    Star, // *
+    Set { negated: bool, items: Vec<SetItem> },
+}
+
+#[derive(Debug, Clone)]
+enum SetItem {
+    Char(char),
+    Range(char, char),
+    Class(String), // [:alpha:] and the like
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Self {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let token = match chars[i] {
+                '\\' if i + 1 < chars.len() => {
+                    i += 1;
+                    Token::Literal(chars[i])
+                }
+                // `**` matches no more than `*`
+                '*' if matches!(tokens.last(), Some(Token::Star)) => {
+                    i += 1;
+                    continue;
+                }
+                '*' => Token::Star,
+                '?' => Token::Any,
+                '[' => match parse_set(&chars[i + 1..]) {
+                    Some((token, len)) => {
+                        i += len;
+                        token
+                    }
+                    // an unclosed `[` is just a character
+                    None => Token::Literal('['),
+                },
+                c => Token::Literal(c),
+            };
+            tokens.push(token);
+            i += 1;
+        }
+        Self { tokens }
+    }
+
+    // Whether the pattern matches the whole text.
+    pub fn matches(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        match_from(&self.tokens, &chars)
+    }
+
+    // The only text the pattern matches, when it has no special characters.
+    pub fn literal(&self) -> Option<String> {
+        self.tokens
+            .iter()
+            .map(|token| match token {
+                Token::Literal(c) => Some(*c),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+// Backslash-quotes the characters that mean something in a pattern, so that
+// quoted text only matches itself.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if "\\*?[]".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Matches left to right, remembering only the last `*`: when the tokens after
+// it fail, that `*` takes one more character and they are tried again. An
+// earlier `*` never needs to take more, as the last one can take it instead,
+// so this is O(tokens * text).
+fn match_from(tokens: &[Token], text: &[char]) -> bool {
+    let (mut t, mut i) = (0, 0); // the next token and the next character
+    let mut star: Option<(usize, usize)> = None; // the token after the last `*`, where its match ends
+    while i < text.len() {
+        match tokens.get(t) {
+            Some(Token::Star) => {
+                t += 1;
+                star = Some((t, i));
+                continue;
+            }
+            Some(token) if token.matches(text[i]) => {
+                t += 1;
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+        let Some((after, end)) = star else {
+            return false;
+        };
+        t = after;
+        i = end + 1;
+        star = Some((after, i));
+    }
+    tokens[t..].iter().all(|token| matches!(token, Token::Star))
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Literal(literal) => c == *literal,
+            Token::Any => true,
+            Token::Star => true,
+            Token::Set { negated, items } => items.iter().any(|item| item.matches(c)) != *negated,
+        }
+    }
+}
+
+impl SetItem {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            SetItem::Char(item) => c == *item,
+            SetItem::Range(low, high) => (*low..=*high).contains(&c),
+            SetItem::Class(class) => match class.as_str() {
+                "alpha" => c.is_alphabetic(),
+                "digit" => c.is_ascii_digit(),
+                "alnum" => c.is_alphanumeric(),
+                "upper" => c.is_uppercase(),
+                "lower" => c.is_lowercase(),
+                "space" => c.is_whitespace(),
+                "blank" => c == ' ' || c == '\t',
+                "punct" => c.is_ascii_punctuation(),
+                "xdigit" => c.is_ascii_hexdigit(),
+                "cntrl" => c.is_control(),
+                "print" => !c.is_control(),
+                "graph" => !c.is_control() && !c.is_whitespace(),
+                _ => false,
+            },
+        }
+    }
+}
+
+// Reads a set from the characters after its `[`. Returns it with the number
+// of characters it took, `]` included, or None when it isn't closed.
+fn parse_set(chars: &[char]) -> Option<(Token, usize)> {
+    let mut i = 0;
+    let negated = matches!(chars.first(), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+    let mut items = Vec::new();
+    // a `]` right at the start belongs to the set
+    let start = i;
+    loop {
+        let c = *chars.get(i)?;
+        match c {
+            ']' if i > start => return Some((Token::Set { negated, items }, i + 1)),
+            '[' if chars.get(i + 1) == Some(&':') => {
+                let rest: String = chars[i + 2..].iter().collect();
+                let end = rest.find(":]")?;
+                items.push(SetItem::Class(rest[..end].to_string()));
+                i += 2 + rest[..end].chars().count() + 2;
+                continue;
+            }
+            _ => {
+                let c = match c {
+                    '\\' => {
+                        i += 1;
+                        *chars.get(i)?
+                    }
+                    c => c,
+                };
+                if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|&end| end != ']') {
+                    items.push(SetItem::Range(c, chars[i + 2]));
+                    i += 3;
+                } else {
+                    items.push(SetItem::Char(c));
+                    i += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Pattern::new(pattern).matches(text)
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*.rs", ".rs"));
+        assert!(!matches("*.rs", "main.rs.bak"));
+        assert!(matches("a*b*c", "aXbYc"));
+        assert!(matches("a**", "abc"));
+        assert!(matches("?.txt", "a.txt"));
+        assert!(!matches("?.txt", ".txt"));
+        assert!(!matches("?", "ab"));
+        assert!(matches("", ""));
+        assert!(!matches("", "a"));
+    }
+
+    #[test]
+    fn sets() {
+        assert!(matches("[ab].rs", "a.rs"));
+        assert!(!matches("[ab].rs", "c.rs"));
+        assert!(matches("[!a].rs", "b.rs"));
+        assert!(!matches("[!a].rs", "a.rs"));
+        assert!(matches("[^a]", "b"));
+        assert!(matches("[a-c]x", "bx"));
+        assert!(!matches("[a-c]x", "dx"));
+        assert!(matches("[]a]", "]"));
+        assert!(matches("[!]a]", "b"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("[[:digit:]]", "7"));
+        assert!(!matches("[[:digit:]]", "x"));
+        assert!(matches("[[:alpha:][:digit:]_]*", "_x1"));
+        assert!(matches("[![:space:]]", "a"));
+        // an unclosed `[` is just a character
+        assert!(matches("[ab", "[ab"));
+        assert!(!matches("[ab", "a"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "a"));
+        assert!(matches("[\\]]", "]"));
+        assert!(matches(&escape("*.[ch]?"), "*.[ch]?"));
+        assert!(!matches(&escape("*.[ch]?"), "a.c!"));
+    }
+
+    #[test]
+    fn many_stars_are_fast() {
+        let start = std::time::Instant::now();
+        let text = "a".repeat(2000);
+        assert!(!matches(&format!("{}*b", "*a".repeat(30)), &text));
+        assert!(matches(&format!("{}*", "*a".repeat(30)), &text));
+        assert!(matches("*a*a*a*a*b", &format!("{}b", "xa".repeat(1000))));
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn literal() {
+        assert_eq!(Pattern::new("main.rs").literal().as_deref(), Some("main.rs"));
+        assert_eq!(Pattern::new("a\\*b").literal().as_deref(), Some("a*b"));
+        assert_eq!(Pattern::new("*.rs").literal(), None);
+        assert_eq!(Pattern::new("[ab]").literal(), None);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::glob::GlobOptions;
+use crate::jobs::{Job, JobState, JobTable};
+use crate::parser::CommandNode;
+use crate::sys;
+use crate::variables::Variables;
+use std::collections::HashMap;
+use std::os::fd::OwnedFd;
+use std::rc::Rc;
+
+// the terminal the shell reads commands from
+const TTY: i32 = libc::STDIN_FILENO;
+
+// Execution environment (shell state), shared by the executor and builtins
+pub struct Shell {
+    pub last_status: i32, // exit status of the last pipeline, read back as `$?`
+    pub jobs: JobTable,   // pipelines running in the background or stopped
+    pub variables: Variables, // shell variables, the exported ones are the environment of programs
+    pub glob_options: GlobOptions, // set with `shopt`
+    pub functions: HashMap<String, Rc<CommandNode>>, // defined with `name() { ...; }`
+    pub positional: Vec<String>, // `$1`, `$2`, ...: the arguments of the running function
+    pub function_depth: usize,   // how many function calls are running
+    pub returning: bool,         // `return` ran, abandon the rest of the function
+    pub job_control: bool, // interactive: each pipeline gets its own process group
+    pub interrupted: bool, // a foreground job died of Ctrl-C, abandon the rest of the line
+    pub source: String,    // the input being run, for messages that point into it
+    pub substitution_status: Option<i32>, // of the last `$(...)` in the command being expanded
+    pub substitution_fds: Vec<OwnedFd>, // the shell's ends of `<(...)` and `>(...)` pipes
+    pub substitution_pids: Vec<libc::pid_t>, // `<(...)` and `>(...)` processes not reaped yet
+    pgid: libc::pid_t,
+    tmodes: Option<libc::termios>, // the shell's own terminal settings
+}
+
+impl Shell {
+    pub fn new() -> Self {
+        let mut variables = Variables::from_env();
+        // whatever the environment said, PWD is where the shell starts
+        if let Ok(dir) = std::env::current_dir() {
+            let _ = variables.set("PWD", dir.to_string_lossy().into_owned());
+            variables.export("PWD");
+        }
+        Self {
+            last_status: 0,
+            jobs: JobTable::new(),
+            variables,
+            glob_options: GlobOptions::default(),
+            functions: HashMap::new(),
+            positional: Vec::new(),
+            function_depth: 0,
+            returning: false,
+            job_control: false,
+            interrupted: false,
+            source: String::new(),
+            substitution_status: None,
+            substitution_fds: Vec::new(),
+            substitution_pids: Vec::new(),
+            pgid: sys::getpgrp(),
+            tmodes: None,
+        }
+    }
+
+    // Whether the commands still to run are skipped: after Ctrl-C killed a
+    // job, or after `return` until the function is left.
+    pub fn abandoning(&self) -> bool {
+        self.interrupted || self.returning
+    }
+
+    // Takes over the terminal when the shell runs interactively, so that
+    // pipelines can be moved between the foreground and the background.
+    pub fn enable_job_control(&mut self) {
+        if !sys::isatty(TTY) {
+            return;
+        }
+        // started in the background: wait until we are brought to the foreground
+        loop {
+            let pgid = sys::getpgrp();
+            if sys::tcgetpgrp(TTY) == pgid {
+                break;
+            }
+            let _ = sys::kill(-pgid, libc::SIGTTIN);
+        }
+        for signal in sys::INTERACTIVE_SIGNALS {
+            sys::ignore_signal(signal);
+        }
+
+        let pid = sys::getpid();
+        // fails when we already lead a session, which is just as good
+        let _ = sys::setpgid(pid, pid);
+        self.pgid = sys::getpgrp();
+        if sys::tcsetpgrp(TTY, self.pgid).is_err() {
+            return;
+        }
+        self.tmodes = sys::tcgetattr(TTY).ok();
+        self.job_control = true;
+    }
+
+    // Runs in a freshly forked child: joins the job's process group (0 starts
+    // a new one), takes the terminal for a foreground job and restores the
+    // default handling of the signals the shell ignores.
+    pub fn enter_job(&self, pgid: libc::pid_t, foreground: bool) {
+        if self.job_control {
+            let _ = sys::setpgid(0, pgid);
+            if foreground {
+                let _ = sys::tcsetpgrp(TTY, sys::getpgrp());
+            }
+        }
+        for signal in sys::INTERACTIVE_SIGNALS {
+            sys::default_signal(signal);
+        }
+        // the Rust runtime ignores SIGPIPE; a job writing to a closed pipe,
+        // builtin or not, should die of it quietly
+        sys::default_signal(libc::SIGPIPE);
+    }
+
+    // Gives the job the terminal and waits until it exits or is stopped.
+    // A stopped job goes to the job table. Returns the exit status.
+    pub fn run_foreground(&mut self, mut job: Job, resume: bool) -> i32 {
+        if self.job_control {
+            let _ = sys::tcsetpgrp(TTY, job.pgid);
+            if let (true, Some(tmodes)) = (resume, &job.tmodes) {
+                let _ = sys::tcsetattr(TTY, tmodes);
+            }
+        }
+        if resume {
+            if let Err(e) = job.resume() {
+                eprintln!("fg: {}", e);
+            }
+        }
+        job.wait();
+
+        let state = job.state();
+        if self.job_control {
+            let _ = sys::tcsetpgrp(TTY, self.pgid);
+            if state == JobState::Stopped {
+                job.tmodes = sys::tcgetattr(TTY).ok();
+            }
+            if let Some(tmodes) = &self.tmodes {
+                let _ = sys::tcsetattr(TTY, tmodes);
+            }
+        }
+
+        match state {
+            JobState::Done(status) => {
+                // the ^C or ^\ echoed by the terminal leaves the cursor mid-line
+                let interrupted = [libc::SIGINT, libc::SIGQUIT].map(|signal| 128 + signal);
+                if self.job_control && interrupted.contains(&status) {
+                    println!();
+                    self.interrupted = true;
+                }
+                status
+            }
+            _ => {
+                let id = self.jobs.add(job);
+                if let Some(line) = self.jobs.describe(id) {
+                    println!("\n{}", line);
+                }
+                128 + libc::SIGTSTP
+            }
+        }
+    }
+
+    // Closes the pipe ends of the process substitutions made since `mark`,
+    // a length of `substitution_fds`, and reaps the substitution processes
+    // that are done. The others are tried again later.
+    pub fn finish_substitutions(&mut self, mark: usize) {
+        self.substitution_fds.truncate(mark);
+        self.substitution_pids
+            .retain(|&pid| matches!(sys::waitpid(pid, libc::WNOHANG), Ok(None)));
+    }
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
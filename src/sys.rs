@@ -0,0 +1,174 @@
+// Thin wrappers around the libc calls used to build pipelines.
+use std::fs::File;
+use std::io::{self, Write};
+use std::mem::ManuallyDrop;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+
+pub enum Fork {
+    Child,
+    Parent(libc::pid_t),
+}
+
+pub fn fork() -> io::Result<Fork> {
+    // flush buffered output first, otherwise the child prints it a second time
+    io::stdout().flush()?;
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(Fork::Child),
+        pid => Ok(Fork::Parent(pid)),
+    }
+}
+
+// Returns (read end, write end). Both ends are close-on-exec so that only the
+// copies dup'ed onto stdin/stdout survive into the programs we run.
+pub fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    unsafe { Ok((OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))) }
+}
+
+// Copies fd to a descriptor above the ones scripts normally use.
+pub fn dup(fd: RawFd) -> io::Result<OwnedFd> {
+    match unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 10) } {
+        -1 => Err(io::Error::last_os_error()),
+        new_fd => Ok(unsafe { OwnedFd::from_raw_fd(new_fd) }),
+    }
+}
+
+pub fn dup2(src: RawFd, dst: RawFd) -> io::Result<()> {
+    if unsafe { libc::dup2(src, dst) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Lets programs the shell runs inherit the descriptor.
+pub fn clear_cloexec(fd: RawFd) -> io::Result<()> {
+    check(unsafe { libc::fcntl(fd, libc::F_SETFD, 0) })
+}
+
+// Writes straight to the descriptor. Unlike io::stdout(), nothing is kept in
+// a buffer when the write fails, to come out later wherever stdout is then.
+pub fn write_all(fd: RawFd, bytes: &[u8]) -> io::Result<()> {
+    // borrowed: the descriptor must stay open after the write
+    let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+    file.write_all(bytes)
+}
+
+// Closes a descriptor the shell doesn't own through an OwnedFd.
+pub fn close(fd: RawFd) -> io::Result<()> {
+    check(unsafe { libc::close(fd) })
+}
+
+pub enum WaitStatus {
+    Exited(i32), // exit status, 128 + signal number when killed by a signal
+    Stopped,
+    Continued,
+}
+
+// Returns None when WNOHANG is given and the child has nothing to report.
+pub fn waitpid(pid: libc::pid_t, options: libc::c_int) -> io::Result<Option<WaitStatus>> {
+    let mut status = 0;
+    loop {
+        match unsafe { libc::waitpid(pid, &mut status, options) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            0 => return Ok(None),
+            _ => break,
+        }
+    }
+    Ok(Some(if libc::WIFSTOPPED(status) {
+        WaitStatus::Stopped
+    } else if libc::WIFCONTINUED(status) {
+        WaitStatus::Continued
+    } else if libc::WIFSIGNALED(status) {
+        WaitStatus::Exited(128 + libc::WTERMSIG(status))
+    } else {
+        WaitStatus::Exited(libc::WEXITSTATUS(status))
+    }))
+}
+
+pub fn kill(pid: libc::pid_t, signal: libc::c_int) -> io::Result<()> {
+    check(unsafe { libc::kill(pid, signal) })
+}
+
+pub fn getpid() -> libc::pid_t {
+    unsafe { libc::getpid() }
+}
+
+pub fn getpgrp() -> libc::pid_t {
+    unsafe { libc::getpgrp() }
+}
+
+pub fn setpgid(pid: libc::pid_t, pgid: libc::pid_t) -> io::Result<()> {
+    check(unsafe { libc::setpgid(pid, pgid) })
+}
+
+pub fn isatty(fd: RawFd) -> bool {
+    unsafe { libc::isatty(fd) == 1 }
+}
+
+pub fn tcgetpgrp(fd: RawFd) -> libc::pid_t {
+    unsafe { libc::tcgetpgrp(fd) }
+}
+
+pub fn tcsetpgrp(fd: RawFd, pgid: libc::pid_t) -> io::Result<()> {
+    check(unsafe { libc::tcsetpgrp(fd, pgid) })
+}
+
+pub fn tcgetattr(fd: RawFd) -> io::Result<libc::termios> {
+    let mut termios = unsafe { std::mem::zeroed() };
+    check(unsafe { libc::tcgetattr(fd, &mut termios) })?;
+    Ok(termios)
+}
+
+pub fn tcsetattr(fd: RawFd, termios: &libc::termios) -> io::Result<()> {
+    check(unsafe { libc::tcsetattr(fd, libc::TCSADRAIN, termios) })
+}
+
+// Signals an interactive shell ignores: keyboard interrupts are meant for the
+// foreground job, and the job control ones would stop the shell itself.
+pub const INTERACTIVE_SIGNALS: [libc::c_int; 5] = [
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGTSTP,
+    libc::SIGTTIN,
+    libc::SIGTTOU,
+];
+
+pub fn ignore_signal(signal: libc::c_int) {
+    unsafe { libc::signal(signal, libc::SIG_IGN) };
+}
+
+pub fn default_signal(signal: libc::c_int) {
+    unsafe { libc::signal(signal, libc::SIG_DFL) };
+}
+
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// io::Error's Display appends " (os error N)", shells print only the message.
+pub fn error_message(err: &io::Error) -> String {
+    let message = err.to_string();
+    match message.find(" (os error") {
+        Some(end) => message[..end].to_string(),
+        None => message,
+    }
+}
+
+// Terminates a forked child without running the parent's cleanup code.
+pub fn exit(status: i32) -> ! {
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+    unsafe { libc::_exit(status) }
+}
@@ -1,137 +1,650 @@
+use std::fmt;
+use std::os::fd::RawFd;
+
+// Where a token came from: its byte range in the input and the line it starts on.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+}
+
+impl Span {
+    // The input line the span starts on, with `^` under the spanned text.
+    pub fn underline(&self, input: &str) -> String {
+        let line_start = input[..self.start].rfind('\n').map_or(0, |at| at + 1);
+        let line_end = input[self.start..]
+            .find('\n')
+            .map_or(input.len(), |at| self.start + at);
+        let text = &input[line_start..line_end];
+        let indent = input[line_start..self.start].chars().count();
+        let width = input[self.start..self.end.min(line_end)].chars().count();
+        format!("{}\n{}{}", text, " ".repeat(indent), "^".repeat(width.max(1)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quoting {
+    Bare,   // unquoted: subject to every expansion, splitting and globbing
+    Single, // '...', or a character escaped with a backslash: taken literally
+    Double, // "...": expanded, but never split or globbed
+}
+
+// A run of characters of a word that were quoted the same way, quotes removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub text: String,
+    pub quoting: Quoting,
+}
+
+// A word as typed, e.g. `a"$b"'c'` is three segments. `""` is a word of its own.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Word {
+    pub segments: Vec<Segment>,
+}
+
+impl Word {
+    // A word made of unquoted text.
+    pub fn bare(text: &str) -> Self {
+        Self {
+            segments: vec![Segment {
+                text: text.to_string(),
+                quoting: Quoting::Bare,
+            }],
+        }
+    }
+
+    // Starts a quoted part, which exists even if nothing is put in it.
+    fn open(&mut self, quoting: Quoting) {
+        self.segments.push(Segment {
+            text: String::new(),
+            quoting,
+        });
+    }
+
+    fn push(&mut self, quoting: Quoting, c: char) {
+        match self.segments.last_mut() {
+            Some(segment) if segment.quoting == quoting => segment.text.push(c),
+            _ => self.segments.push(Segment {
+                text: c.to_string(),
+                quoting,
+            }),
+        }
+    }
+
+    // The text with the quotes removed.
+    pub fn text(&self) -> String {
+        self.segments.iter().map(|segment| segment.text.as_str()).collect()
+    }
+
+    // The text of an entirely unquoted word. Only those can be reserved
+    // words or operators like `{` and `!`.
+    pub fn as_bare(&self) -> Option<&str> {
+        match self.segments.as_slice() {
+            [Segment {
+                text,
+                quoting: Quoting::Bare,
+            }] => Some(text),
+            _ => None,
+        }
+    }
+}
+
+// The word with its quotes put back.
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for segment in &self.segments {
+            match segment.quoting {
+                Quoting::Bare => write!(f, "{}", segment.text)?,
+                Quoting::Single => write!(f, "'{}'", segment.text)?,
+                Quoting::Double => write!(f, "\"{}\"", segment.text)?,
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
-pub enum Token {
-    Word(String),
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum TokenKind {
+    Word(Word),
     Pipe,              // |
+    PipeBoth,          // |&, pipes stderr along with stdout
     Ampersand,         // &
+    Semicolon,         // ;
+    DoubleSemicolon,   // ;;, ends an arm of `case`
+    AndIf,             // &&
+    OrIf,              // ||
+    LParen,            // (
+    RParen,            // )
+    Newline,           // \n, ends a command like `;` does
+    IoNumber(RawFd),   // the `2` of `2>`: digits right before a redirection operator
     RedirectIn,        // <
-    RedirectOut,       // > or 1>
-    RedirectErrOut,    // 2>
-    RedirectAppend,    // >> or 1>>
-    RedirectErrAppend, // 2>>
+    HereDoc {
+        strip: bool,        // <<- removes leading tabs from the body and the delimiter line
+        body: Option<Word>, // None until the delimiter line has been read
+    },
+    HereString, // <<<
+    Arithmetic(String), // ((expression)) where a command starts
+    Unfinished, // ends input that stops inside quotes or `$(`, more lines may finish it
+    RedirectOut,       // >
+    RedirectAppend,    // >>
+    DupOut,            // >&
+    DupIn,             // <&
+    RedirectBoth,      // &>, stdout and stderr to the same file
+    AppendBoth,        // &>>
+}
+
+// The token as it would be written on the command line, used in syntax errors.
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            TokenKind::Word(word) => return write!(f, "{}", word),
+            TokenKind::Pipe => "|",
+            TokenKind::PipeBoth => "|&",
+            TokenKind::Ampersand => "&",
+            TokenKind::Semicolon => ";",
+            TokenKind::DoubleSemicolon => ";;",
+            TokenKind::AndIf => "&&",
+            TokenKind::OrIf => "||",
+            TokenKind::LParen => "(",
+            TokenKind::RParen => ")",
+            TokenKind::Newline => "newline",
+            TokenKind::IoNumber(fd) => return write!(f, "{}", fd),
+            TokenKind::RedirectIn => "<",
+            TokenKind::HereDoc { strip: false, .. } => "<<",
+            TokenKind::HereDoc { strip: true, .. } => "<<-",
+            TokenKind::HereString => "<<<",
+            TokenKind::Arithmetic(expression) => return write!(f, "(({}))", expression),
+            TokenKind::Unfinished => "end of input",
+            TokenKind::RedirectOut => ">",
+            TokenKind::RedirectAppend => ">>",
+            TokenKind::DupOut => ">&",
+            TokenKind::DupIn => "<&",
+            TokenKind::RedirectBoth => "&>",
+            TokenKind::AppendBoth => "&>>",
+        };
+        write!(f, "{}", text)
+    }
 }
 
 pub struct Tokenizer;
 
 impl Tokenizer {
-    pub fn tokenize(input: &str) -> Vec<Token> {
+    // Splits the input into tokens. `first_line` is the number of its first
+    // line, for input that continues a script.
+    pub fn tokenize(input: &str, first_line: usize) -> Vec<Token> {
         let mut tokens = Vec::new();
-        let mut current = String::new();
+        let mut current = Word::default();
+        let mut word = Span::default(); // where `current` started
+        let mut line = first_line;
         let mut in_single_quotes = false;
         let mut in_double_quotes = false;
+        let mut here_docs = Vec::new(); // indexes of `<<` tokens whose body comes after this line
+        let mut unfinished = false;
 
-        let mut chars = input.chars().peekable();
-        while let Some(c) = chars.next() {
-            match c {
+        let mut chars = input.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let quoted = in_single_quotes || in_double_quotes;
+            let quoting = match (in_single_quotes, in_double_quotes) {
+                (true, _) => Quoting::Single,
+                (_, true) => Quoting::Double,
+                _ => Quoting::Bare,
+            };
+            if current.segments.is_empty() && !quoted {
+                word = Span { start: i, end: i, line };
+            }
+            let at_line = line;
+            if c == '\n' {
+                line += 1;
+            }
+            // `$(...)`, `${...}` and `` `...` `` outside single quotes, `<(...)` and `>(...)` unquoted
+            let substitution = match c {
+                '`' => !in_single_quotes,
+                '$' => !in_single_quotes && (input[i..].starts_with("$(") || input[i..].starts_with("${")),
+                '<' | '>' => !quoted && input[i + 1..].starts_with('('),
+                _ => false,
+            };
+            let kind = match c {
                 '\'' if !in_double_quotes => {
                     in_single_quotes = !in_single_quotes;
+                    if in_single_quotes {
+                        current.open(Quoting::Single);
+                    }
                     continue;
                 }
                 '"' if !in_single_quotes => {
                     in_double_quotes = !in_double_quotes;
-                    continue;
-                }
-                ' ' if !in_single_quotes && !in_double_quotes => {
-                    if !current.is_empty() {
-                        tokens.push(Token::Word(current.clone()));
-                        current.clear();
+                    if in_double_quotes {
+                        current.open(Quoting::Double);
                     }
                     continue;
                 }
-                '1' => {
-                    if let Some(&'>') = chars.peek() {
-                        chars.next();
-                        if let Some(&'>') = chars.peek() {
-                            tokens.push(Token::RedirectAppend);
-                            chars.next();
-                        } else {
-                            tokens.push(Token::RedirectOut);
-                        }
-                    } else {
-                        current.push(c)
-                    }
+                ' ' | '\t' if !quoted => {
+                    Self::end_word(&mut tokens, &mut current, word, i);
                     continue;
                 }
-                '2' => {
-                    if let Some(&'>') = chars.peek() {
-                        chars.next();
-                        if let Some(&'>') = chars.peek() {
-                            tokens.push(Token::RedirectErrAppend);
-                            chars.next();
-                        } else {
-                            tokens.push(Token::RedirectErrOut);
-                        }
-                    } else {
-                        current.push(c)
-                    }
+                _ if substitution => {
+                    // a substitution is kept as typed, `)` and all, the
+                    // expansion takes care of it
+                    let len = substitution_len(&input[i..], in_double_quotes).unwrap_or_else(|| {
+                        unfinished = true;
+                        input.len() - i
+                    });
+                    let raw = &input[i..i + len];
+                    raw.chars().for_each(|c| current.push(quoting, c));
+                    line += raw.matches('\n').count();
+                    while chars.next_if(|&(next, _)| next < i + len).is_some() {}
                     continue;
                 }
-
-                '>' if !in_single_quotes && !in_double_quotes => {
-                    if !current.is_empty() {
-                        tokens.push(Token::Word(current.clone()));
-                        current.clear();
+                // `((a); b)` is a subshell in a subshell after all
+                '(' if !quoted
+                    && current.segments.is_empty()
+                    && input[i + 1..].starts_with('(')
+                    && Self::command_start(&tokens)
+                    && substitution_len(&input[i..], false)
+                        .map_or(true, |len| input[i..i + len].ends_with("))")) =>
+                {
+                    let len = substitution_len(&input[i..], false).unwrap_or_else(|| {
+                        unfinished = true;
+                        input.len() - i
+                    });
+                    let raw = &input[i..i + len];
+                    line += raw.matches('\n').count();
+                    while chars.next_if(|&(next, _)| next < i + len).is_some() {}
+                    let expression = raw[2..].strip_suffix("))").unwrap_or(&raw[2..]);
+                    TokenKind::Arithmetic(expression.to_string())
+                }
+                '>' | '<' | '|' | '&' | ';' | '(' | ')' | '\n' if !quoted => {
+                    // an unquoted number touching `<` or `>` is the fd to redirect
+                    let io_number = match current.as_bare() {
+                        Some(digits) if "<>".contains(c) && digits.bytes().all(|b| b.is_ascii_digit()) => {
+                            digits.parse().ok()
+                        }
+                        _ => None,
+                    };
+                    if let Some(fd) = io_number {
+                        current = Word::default();
+                        let span = Span { end: i, ..word };
+                        tokens.push(Token { kind: TokenKind::IoNumber(fd), span });
                     }
-                    if let Some(&'>') = chars.peek() {
-                        chars.next(); // that mean  >> exist not only >
-                        tokens.push(Token::RedirectAppend);
-                        current.clear();
-                    } else {
-                        tokens.push(Token::RedirectOut); // >  only exist
-                        current.clear();
+                    Self::end_word(&mut tokens, &mut current, word, i);
+                    let mut eat = |expected| chars.next_if(|&(_, next)| next == expected).is_some();
+                    match c {
+                        '>' if eat('>') => TokenKind::RedirectAppend,
+                        '>' if eat('&') => TokenKind::DupOut,
+                        '>' => TokenKind::RedirectOut,
+                        '<' if eat('<') => {
+                            if eat('<') {
+                                TokenKind::HereString
+                            } else {
+                                here_docs.push(tokens.len());
+                                TokenKind::HereDoc { strip: eat('-'), body: None }
+                            }
+                        }
+                        '<' if eat('&') => TokenKind::DupIn,
+                        '<' => TokenKind::RedirectIn,
+                        '|' if eat('|') => TokenKind::OrIf,
+                        '|' if eat('&') => TokenKind::PipeBoth,
+                        '|' => TokenKind::Pipe,
+                        '&' if eat('&') => TokenKind::AndIf,
+                        '&' if eat('>') => match eat('>') {
+                            true => TokenKind::AppendBoth,
+                            false => TokenKind::RedirectBoth,
+                        },
+                        '&' => TokenKind::Ampersand,
+                        ';' if eat(';') => TokenKind::DoubleSemicolon,
+                        ';' => TokenKind::Semicolon,
+                        '(' => TokenKind::LParen,
+                        ')' => TokenKind::RParen,
+                        _ => TokenKind::Newline,
                     }
-                    continue;
                 }
-                '<' if !in_single_quotes && !in_double_quotes => {
-                    if !current.is_empty() {
-                        tokens.push(Token::Word(current.clone()));
-                        current.clear();
+                '\\' if !quoted => {
+                    // Take next char literally if exists
+                    if let Some((_, next)) = chars.next() {
+                        current.push(Quoting::Single, next);
                     }
-                    tokens.push(Token::RedirectIn);
                     continue;
                 }
-                '|' if !in_single_quotes && !in_double_quotes => {
-                    if !current.is_empty() {
-                        tokens.push(Token::Word(current.clone()));
-                        current.clear();
+                '\\' if in_double_quotes => {
+                    // In double quotes, only certain characters can be escaped
+                    if let Some((_, next)) = chars.next_if(|&(_, next)| "\"\\$`".contains(next)) {
+                        current.push(Quoting::Single, next);
+                    } else {
+                        current.push(quoting, c); // treat backslash literally
                     }
-                    tokens.push(Token::Pipe);
                     continue;
                 }
-                '&' if !in_single_quotes && !in_double_quotes => {
-                    if !current.is_empty() {
-                        tokens.push(Token::Word(current.clone()));
-                        current.clear();
-                    }
-                    tokens.push(Token::Ampersand);
+                _ => {
+                    current.push(quoting, c);
                     continue;
                 }
+            };
+            let end = chars.peek().map_or(input.len(), |&(at, _)| at);
+            let span = Span { start: i, end, line: at_line };
+            tokens.push(Token { kind, span });
 
-                '\\' if !in_single_quotes && !in_double_quotes => {
-                    // Take next char literally if exists
-                    if let Some(next) = chars.next() {
-                        current.push(next);
+            // here-document bodies follow the line their `<<` is on
+            if c == '\n' && !here_docs.is_empty() {
+                let mut at = end;
+                for index in here_docs.drain(..) {
+                    at = Self::read_here_doc(input, at, &mut tokens, index);
+                }
+                line += input[end..at].matches('\n').count();
+                while chars.next_if(|&(next, _)| next < at).is_some() {}
+            }
+        }
+        Self::end_word(&mut tokens, &mut current, word, input.len());
+        if unfinished || in_single_quotes || in_double_quotes {
+            let span = Span { start: input.len(), end: input.len(), line };
+            tokens.push(Token { kind: TokenKind::Unfinished, span });
+        }
+        tokens
+    }
+
+    // Reads the body of the here-document started by tokens[index] from the
+    // lines at `input[from..]`, and returns where the line after its
+    // delimiter starts. The body stays None if the input ends first.
+    fn read_here_doc(input: &str, from: usize, tokens: &mut [Token], index: usize) -> usize {
+        let Some(Token {
+            kind: TokenKind::Word(delimiter),
+            ..
+        }) = tokens.get(index + 1)
+        else {
+            // no delimiter, the parser reports it
+            return from;
+        };
+        // quoting any part of the delimiter turns off expansion in the body
+        let expand = delimiter.as_bare().is_some();
+        let delimiter = delimiter.text();
+        let Token {
+            kind: TokenKind::HereDoc { strip, body },
+            ..
+        } = &mut tokens[index]
+        else {
+            return from;
+        };
+
+        let mut text = String::new();
+        let mut at = from;
+        while at < input.len() {
+            let line_end = input[at..].find('\n').map_or(input.len(), |n| at + n);
+            let mut line = &input[at..line_end];
+            at = (line_end + 1).min(input.len());
+            if *strip {
+                line = line.trim_start_matches('\t');
+            }
+            if line == delimiter {
+                *body = Some(Self::here_doc_word(&text, expand));
+                return at;
+            }
+            text.push_str(line);
+            text.push('\n');
+        }
+        input.len()
+    }
+
+    // The body as a word: expanded like double-quoted text, where a backslash
+    // only escapes `$`, `` ` ``, `\` and newlines, or taken literally.
+    fn here_doc_word(text: &str, expand: bool) -> Word {
+        let mut word = Word::default();
+        if !expand {
+            word.open(Quoting::Single);
+            text.chars().for_each(|c| word.push(Quoting::Single, c));
+            return word;
+        }
+        word.open(Quoting::Double);
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match chars.next_if(|&next| c == '\\' && "$`\\\n".contains(next)) {
+                Some('\n') => {} // a line continuation
+                Some(next) => word.push(Quoting::Single, next),
+                None => word.push(Quoting::Double, c),
+            }
+        }
+        word
+    }
+
+    // The word of an operator like `${name:-word}`, read as a single word:
+    // quotes are removed, blanks and operators are ordinary characters. In a
+    // `${...}` between double quotes, `quoted`, so are single quotes. What
+    // isn't quoted inside the braces stays Bare, so patterns work there.
+    pub fn operand(text: &str, quoted: bool) -> Word {
+        let mut word = Word::default();
+        let mut in_double_quotes = false;
+        let mut i = 0;
+        while let Some(c) = text[i..].chars().next() {
+            let rest = &text[i..];
+            let quoting = if in_double_quotes { Quoting::Double } else { Quoting::Bare };
+            match c {
+                '\\' => {
+                    // between double quotes, only a few characters can be escaped
+                    let next = rest[1..].chars().next();
+                    match next.filter(|&next| !(quoted || in_double_quotes) || "$`\"\\}".contains(next)) {
+                        Some(next) => {
+                            word.push(Quoting::Single, next);
+                            i += next.len_utf8();
+                        }
+                        None => word.push(quoting, c),
                     }
+                }
+                '\'' if !quoted && !in_double_quotes => {
+                    let len = rest[1..].find('\'').unwrap_or(rest.len() - 1);
+                    word.open(Quoting::Single);
+                    rest[1..1 + len].chars().for_each(|c| word.push(Quoting::Single, c));
+                    i = (i + len + 2).min(text.len());
                     continue;
                 }
-                '\\' if in_double_quotes => {
-                    // In double quotes, only certain characters can be escaped
-                    if let Some(&next) = chars.peek() {
-                        if next == '"' || next == '\\' || next == '$' || next == '`' {
-                            chars.next(); // consume the next character
-                            current.push(next);
-                            continue;
-                        }
+                '"' => {
+                    in_double_quotes = !in_double_quotes;
+                    if in_double_quotes {
+                        word.open(Quoting::Double);
                     }
-                    current.push(c); // treat backslash literally
+                }
+                '$' | '`' if c == '`' || rest.starts_with("$(") || rest.starts_with("${") => {
+                    let len = substitution_len(rest, quoted || in_double_quotes).unwrap_or(rest.len());
+                    rest[..len].chars().for_each(|c| word.push(quoting, c));
+                    i += len;
                     continue;
                 }
-                _ => {}
+                _ => word.push(quoting, c),
             }
-            current.push(c);
+            i += c.len_utf8();
         }
-        if !current.is_empty() {
-            tokens.push(Token::Word(current.clone()));
+        word
+    }
+
+    // Whether a command can start after these tokens, where `((` begins an
+    // arithmetic command rather than two subshells.
+    fn command_start(tokens: &[Token]) -> bool {
+        let kinds: Vec<&TokenKind> = tokens.iter().rev().take(2).map(|token| &token.kind).collect();
+        // the `()` of a function definition, its body follows
+        if let [TokenKind::RParen, TokenKind::LParen] = kinds[..] {
+            return true;
         }
-        tokens
+        match tokens.last().map(|token| &token.kind) {
+            None => true,
+            Some(TokenKind::Word(word)) => matches!(
+                word.as_bare(),
+                Some("if" | "then" | "elif" | "else" | "while" | "until" | "do" | "{" | "!")
+            ),
+            Some(kind) => matches!(
+                kind,
+                TokenKind::Pipe
+                    | TokenKind::PipeBoth
+                    | TokenKind::Ampersand
+                    | TokenKind::Semicolon
+                    | TokenKind::DoubleSemicolon
+                    | TokenKind::AndIf
+                    | TokenKind::OrIf
+                    | TokenKind::LParen
+                    | TokenKind::Newline
+            ),
+        }
+    }
+
+    // Pushes the word being built, if any, as ending at byte offset `end`.
+    fn end_word(tokens: &mut Vec<Token>, current: &mut Word, mut span: Span, end: usize) {
+        if !current.segments.is_empty() {
+            span.end = end;
+            tokens.push(Token {
+                kind: TokenKind::Word(std::mem::take(current)),
+                span,
+            });
+        }
+    }
+}
+
+// The length of the `$(...)`, `${...}`, `` `...` ``, `<(...)` or `>(...)`
+// that `text` starts with, closing character included, skipping over the
+// quotes and substitutions nested in it. None when it isn't closed. In a
+// `${...}` between double quotes, `quoted`, single quotes are plain characters.
+// Also measures the `((...))` of an arithmetic command.
+pub fn substitution_len(text: &str, quoted: bool) -> Option<usize> {
+    let backquoted = text.starts_with('`');
+    let braced = text.starts_with("${");
+    let (open, close) = if braced { ('{', '}') } else { ('(', ')') };
+    let mut i = if backquoted { 1 } else { 2 };
+    let mut depth = if text.starts_with("((") { 2 } else { 1 }; // open parentheses or braces
+    let mut in_double_quotes = false;
+    while let Some(c) = text[i..].chars().next() {
+        let nested_quoted = in_double_quotes || braced && quoted;
+        match c {
+            '\\' => {
+                i += 1 + text[i + 1..].chars().next().map_or(0, char::len_utf8);
+                continue;
+            }
+            '`' if backquoted => return Some(i + 1),
+            '\'' if !backquoted && !nested_quoted => {
+                i += 1 + text[i + 1..].find('\'')? + 1;
+                continue;
+            }
+            '"' if !backquoted => in_double_quotes = !in_double_quotes,
+            '`' => {
+                i += substitution_len(&text[i..], nested_quoted)?;
+                continue;
+            }
+            '$' if text[i..].starts_with("$(") || text[i..].starts_with("${") => {
+                i += substitution_len(&text[i..], nested_quoted)?;
+                continue;
+            }
+            _ if backquoted || in_double_quotes => {}
+            _ if c == open => depth += 1,
+            _ if c == close => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+        i += c.len_utf8();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(input: &str) -> Vec<TokenKind> {
+        Tokenizer::tokenize(input, 1).into_iter().map(|token| token.kind).collect()
+    }
+
+    fn words(input: &str) -> Vec<Word> {
+        kinds(input)
+            .into_iter()
+            .filter_map(|kind| match kind {
+                TokenKind::Word(word) => Some(word),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn segment(text: &str, quoting: Quoting) -> Segment {
+        Segment {
+            text: text.to_string(),
+            quoting,
+        }
+    }
+
+    #[test]
+    fn empty_quotes_make_a_word() {
+        let words = words(r#"echo "" x"#);
+        assert_eq!(words.len(), 3);
+        assert_eq!(words[1].segments, [segment("", Quoting::Double)]);
+        assert_eq!(words[2], Word::bare("x"));
+    }
+
+    #[test]
+    fn segments_keep_their_quoting() {
+        let words = words(r#"a"$b"'c'"#);
+        assert_eq!(
+            words[0].segments,
+            [
+                segment("a", Quoting::Bare),
+                segment("$b", Quoting::Double),
+                segment("c", Quoting::Single),
+            ]
+        );
+    }
+
+    #[test]
+    fn escaped_characters_are_single_quoted() {
+        let words = words(r"a\*b \$x");
+        assert_eq!(
+            words[0].segments,
+            [
+                segment("a", Quoting::Bare),
+                segment("*", Quoting::Single),
+                segment("b", Quoting::Bare),
+            ]
+        );
+        assert_eq!(words[1].segments, [segment("$", Quoting::Single), segment("x", Quoting::Bare)]);
+    }
+
+    #[test]
+    fn escaped_quotes_inside_double_quotes() {
+        let words = words(r#""\"\"""#);
+        assert_eq!(words.len(), 1);
+        assert_eq!(
+            words[0].segments,
+            [segment("", Quoting::Double), segment(r#""""#, Quoting::Single)]
+        );
+    }
+
+    #[test]
+    fn digits_in_a_word_are_not_an_io_number() {
+        let kinds = kinds("echo file2>out");
+        assert!(matches!(&kinds[1], TokenKind::Word(word) if *word == Word::bare("file2")));
+        assert!(matches!(kinds[2], TokenKind::RedirectOut));
+        assert!(matches!(&kinds[3], TokenKind::Word(word) if *word == Word::bare("out")));
+    }
+
+    #[test]
+    fn quoted_digits_are_not_an_io_number() {
+        let kinds = kinds(r#"echo "2">x"#);
+        assert!(matches!(&kinds[1], TokenKind::Word(word) if word.text() == "2"));
+        assert!(matches!(kinds[2], TokenKind::RedirectOut));
+    }
+
+    #[test]
+    fn io_numbers() {
+        let kinds = kinds("cmd 3>log 0<in 2>&1");
+        assert!(matches!(kinds[1], TokenKind::IoNumber(3)));
+        assert!(matches!(kinds[2], TokenKind::RedirectOut));
+        assert!(matches!(kinds[4], TokenKind::IoNumber(0)));
+        assert!(matches!(kinds[5], TokenKind::RedirectIn));
+        assert!(matches!(kinds[7], TokenKind::IoNumber(2)));
+        assert!(matches!(kinds[8], TokenKind::DupOut));
+        // separated from the operator, the number is an argument
+        let kinds = self::kinds("echo 2 >x");
+        assert!(matches!(&kinds[1], TokenKind::Word(word) if *word == Word::bare("2")));
     }
 }
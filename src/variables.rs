@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Default)]
+pub struct Variable {
+    pub value: Option<String>, // None when only its attributes were given (`export NAME`)
+    pub exported: bool,        // passed on to the programs the shell runs
+    pub readonly: bool,
+}
+
+// The shell's variables, kept sorted by name for `export` and `readonly` to list.
+#[derive(Debug, Default)]
+pub struct Variables {
+    table: BTreeMap<String, Variable>,
+}
+
+impl Variables {
+    // Starts with the environment the shell was given, all of it exported.
+    // Entries that aren't valid UTF-8 are left out.
+    pub fn from_env() -> Self {
+        let table = std::env::vars_os()
+            .filter_map(|(name, value)| {
+                let variable = Variable {
+                    value: Some(value.into_string().ok()?),
+                    exported: true,
+                    readonly: false,
+                };
+                Some((name.into_string().ok()?, variable))
+            })
+            .collect();
+        Self { table }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.table.get(name)?.value.as_deref()
+    }
+
+    pub fn set(&mut self, name: &str, value: String) -> Result<(), String> {
+        let variable = self.table.entry(name.to_string()).or_default();
+        if variable.readonly {
+            return Err(format!("{}: readonly variable", name));
+        }
+        variable.value = Some(value);
+        Ok(())
+    }
+
+    pub fn unset(&mut self, name: &str) -> Result<(), String> {
+        if self.table.get(name).is_some_and(|variable| variable.readonly) {
+            return Err(format!("{}: cannot unset: readonly variable", name));
+        }
+        self.table.remove(name);
+        Ok(())
+    }
+
+    pub fn export(&mut self, name: &str) {
+        self.table.entry(name.to_string()).or_default().exported = true;
+    }
+
+    pub fn make_readonly(&mut self, name: &str) {
+        self.table.entry(name.to_string()).or_default().readonly = true;
+    }
+
+    // The exported variables that have a value, the environment of the programs the shell runs.
+    pub fn exported(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.table.iter().filter_map(|(name, variable)| match &variable.value {
+            Some(value) if variable.exported => Some((name.as_str(), value.as_str())),
+            _ => None,
+        })
+    }
+
+    // Every variable, in name order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Variable)> {
+        self.table.iter().map(|(name, variable)| (name.as_str(), variable))
+    }
+}
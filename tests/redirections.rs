@@ -0,0 +1,71 @@
+// Runs scripts through the shell binary to check that redirections apply
+// left to right and that builtins leave the shell's own descriptors as they were.
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+// A fresh directory for the test, removed first if a previous run left it.
+fn directory(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("shell-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run(dir: &Path, script: &str) -> Output {
+    let mut shell = Command::new(env!("CARGO_BIN_EXE_codecrafters-shell"))
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    shell.stdin.take().unwrap().write_all(script.as_bytes()).unwrap();
+    shell.wait_with_output().unwrap()
+}
+
+fn read(dir: &Path, file: &str) -> String {
+    fs::read_to_string(dir.join(file)).unwrap()
+}
+
+#[test]
+fn every_target_is_opened_and_the_last_one_wins() {
+    let dir = directory("last-wins");
+    run(&dir, "echo hello > a > b\n");
+    assert_eq!(read(&dir, "a"), "");
+    assert_eq!(read(&dir, "b"), "hello\n");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn duplication_copies_the_descriptor_as_it_is_then() {
+    let dir = directory("dup-order");
+    let output = run(
+        &dir,
+        "sh -c 'echo out; echo err >&2' > both 2>&1\nsh -c 'echo out; echo err >&2' 2>&1 > only\n",
+    );
+    // the stderr of the first goes to the file, of the second where stdout was
+    assert_eq!(read(&dir, "both"), "out\nerr\n");
+    assert_eq!(read(&dir, "only"), "out\n");
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("err\n"));
+    assert!(output.stderr.is_empty());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn builtins_restore_the_shell_descriptors() {
+    let dir = directory("restore");
+    fs::write(dir.join("in"), "input\n").unwrap();
+    let output = run(
+        &dir,
+        "echo hi < in > out 2> err\ntype nosuchcommand < in > out2 2> err2\necho restored\necho warning >&2\n",
+    );
+    assert_eq!(read(&dir, "out"), "hi\n");
+    assert_eq!(read(&dir, "err"), "");
+    assert_eq!(read(&dir, "err2"), "nosuchcommand: not found\n");
+    // the shell still reads its script from stdin and writes to its stdout and stderr
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("restored\n"));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "warning\n");
+    fs::remove_dir_all(dir).unwrap();
+}